@@ -0,0 +1,120 @@
+// Yeahbut October 2026
+
+use std::sync::atomic::Ordering;
+use tokio::io::{self, AsyncBufReadExt, BufReader};
+
+use crate::chat;
+use crate::sessions::{self, SessionMessage, TransferOutcome};
+
+const DEFAULT_KICK_REASON: &str = "Kicked by an admin";
+
+const HELP: &str = "\
+Admin commands:
+  list                     Show every connected player
+  broadcast <message>      Send a message to every player
+  tell <player> <message>  Send a message to one player
+  kick <player> [reason]   Disconnect a player
+  transfer <player|all> <host[:port]>
+                           Move players to another server address
+  help                     Show this message";
+
+fn list_players() -> String {
+    let players = sessions::online_players();
+    let mut lines = vec![format!("{} player(s) online", players.len())];
+    for player in players {
+        let uuid = player.player_uuid
+            .map(sessions::format_uuid)
+            .unwrap_or_else(|| "no uuid".to_string());
+        lines.push(format!(
+            "  {} ({}) from {} via {} on {}, {}m, {}B up, {}B down",
+            player.name,
+            uuid,
+            player.client_addr,
+            player.listener,
+            player.backend,
+            player.connected_for().as_secs() / 60,
+            player.bytes.to_backend.load(Ordering::Relaxed),
+            player.bytes.to_client.load(Ordering::Relaxed),
+        ));
+    }
+    lines.join("\n")
+}
+
+fn announcement(message: &str) -> serde_json::Value {
+    chat::text(message, "gold")
+}
+
+pub fn run_command(line: &str) -> String {
+    let line = line.trim();
+    let (command, rest) = match line.split_once(' ') {
+        Some((command, rest)) => (command, rest.trim()),
+        None => (line, ""),
+    };
+
+    match command.to_lowercase().as_str() {
+        "" => String::new(),
+        "help" => HELP.to_string(),
+        "list" => list_players(),
+        "broadcast" if !rest.is_empty() => {
+            let count = sessions::broadcast(&announcement(rest));
+            format!("Broadcast sent to {} player(s)", count)
+        },
+        "tell" => match rest.split_once(' ') {
+            Some((name, message)) => {
+                if sessions::send_to(
+                    name, SessionMessage::Chat(announcement(message.trim()))) {
+                        format!("Message sent to {}", name)
+                } else {
+                    format!("{} is not connected", name)
+                }
+            },
+            None => "Usage: tell <player> <message>".to_string(),
+        },
+        "kick" if !rest.is_empty() => {
+            let (name, reason) = match rest.split_once(' ') {
+                Some((name, reason)) => (name, reason.trim()),
+                None => (rest, DEFAULT_KICK_REASON),
+            };
+            if sessions::send_to(name, SessionMessage::Kick(reason.to_string())) {
+                format!("Kicked {}", name)
+            } else {
+                format!("{} is not connected", name)
+            }
+        },
+        "transfer" => match rest.split_once(' ') {
+            Some((name, address)) => match sessions::parse_address(
+                address.trim()) {
+                    Some((host, port)) if name == "all" => {
+                        let (sent, skipped) =
+                            sessions::transfer_all(&host, port);
+                        format!("Transferring {} player(s) to {}:{}, skipped \
+{} on clients older than 1.20.5", sent, host, port, skipped)
+                    },
+                    Some((host, port)) => match sessions::transfer(
+                        name, &host, port) {
+                            TransferOutcome::Sent => format!(
+                                "Transferring {} to {}:{}", name, host, port),
+                            TransferOutcome::ClientTooOld => format!(
+                                "Can't transfer {}, their client is too old",
+                                name),
+                            TransferOutcome::NotConnected =>
+                                format!("{} is not connected", name),
+                    },
+                    None => format!("Invalid address: {}", address.trim()),
+            },
+            None => "Usage: transfer <player|all> <host[:port]>".to_string(),
+        },
+        _ => format!("Unknown or incomplete command: {}\n{}", line, HELP),
+    }
+}
+
+// Reads admin commands from the proxy's standard input
+pub async fn console() {
+    let mut lines = BufReader::new(io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let output = run_command(&line);
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+// Yeahbut October 2026
+
+use serde_json::{json, Value};
+
+use crate::minimessage;
+use crate::packet_ids;
+use crate::raw_packet;
+
+// Text components are sent as JSON strings before 1.20.3 and as network NBT
+// from 1.20.3 (765) on
+const PROTOCOL_NBT_COMPONENTS: i32 = 765;
+
+pub fn text(message: &str, color: &str) -> Value {
+    json!({
+        "text": message,
+        "color": color,
+    })
+}
+
+const CONTENT_KEYS: [&str; 6] =
+    ["text", "translate", "score", "selector", "keybind", "nbt"];
+const DECORATION_KEYS: [&str; 5] =
+    ["bold", "italic", "underlined", "strikethrough", "obfuscated"];
+
+pub fn validate_component(component: &Value) -> Result<(), String> {
+    match component {
+        Value::String(_) => Ok(()),
+        Value::Array(components) => {
+            if components.is_empty() {
+                return Err("empty component list".to_string());
+            }
+            components.iter().try_for_each(validate_component)
+        },
+        Value::Object(map) => {
+            if !CONTENT_KEYS.iter().any(|key| map.contains_key(*key)) {
+                return Err(format!("component has no content: {}", component));
+            }
+            if let Some(text) = map.get("text") {
+                if !text.is_string() {
+                    return Err(format!("text is not a string: {}", text));
+                }
+            }
+            if let Some(color) = map.get("color") {
+                match color.as_str() {
+                    Some(color) if minimessage::is_valid_color(color) => {},
+                    _ => return Err(format!("invalid color: {}", color)),
+                }
+            }
+            for key in DECORATION_KEYS {
+                if let Some(value) = map.get(key) {
+                    if !value.is_boolean() {
+                        return Err(format!("{} is not a boolean", key));
+                    }
+                }
+            }
+            match map.get("extra") {
+                Some(extra) if extra.is_array() => validate_component(extra),
+                Some(extra) => Err(format!("extra is not a list: {}", extra)),
+                None => Ok(()),
+            }
+        },
+        _ => Err(format!("not a component: {}", component)),
+    }
+}
+
+pub fn write_component(data: &mut Vec<u8>, protocol: i32, component: &Value) {
+    if protocol >= PROTOCOL_NBT_COMPONENTS {
+        write_nbt_component(data, component);
+    } else {
+        raw_packet::write_string(data, &component.to_string());
+    }
+}
+
+pub fn system_chat_packet(protocol: i32, component: &Value) -> Option<Vec<u8>> {
+    let mut data = raw_packet::new_packet(
+        packet_ids::play::clientbound::system_chat(protocol)?);
+    write_component(&mut data, protocol, component);
+    // Overlay (action bar)
+    data.push(0);
+    Some(data)
+}
+
+pub fn disconnect_packet(protocol: i32, component: &Value) -> Option<Vec<u8>> {
+    let mut data = raw_packet::new_packet(
+        packet_ids::play::clientbound::disconnect(protocol)?);
+    write_component(&mut data, protocol, component);
+    Some(data)
+}
+
+pub fn configuration_disconnect_packet(
+    protocol: i32,
+    component: &Value,
+) -> Option<Vec<u8>> {
+    let mut data = raw_packet::new_packet(
+        packet_ids::configuration::clientbound::disconnect(protocol)?);
+    write_component(&mut data, protocol, component);
+    Some(data)
+}
+
+const LEGACY_DECORATION_CODES: [(&str, char); 5] = [
+    ("obfuscated", 'k'), ("bold", 'l'), ("strikethrough", 'm'),
+    ("underlined", 'n'), ("italic", 'o'),
+];
+
+// Flattens a component to a string with section sign formatting codes for
+// clients older than 1.7. Hex colors have no legacy code and are dropped.
+pub fn legacy_text(component: &Value) -> String {
+    let mut text = String::new();
+    match component {
+        Value::String(string) => text.push_str(string),
+        Value::Array(components) => components.iter()
+            .for_each(|component| text.push_str(&legacy_text(component))),
+        Value::Object(map) => {
+            let color = map.get("color").and_then(Value::as_str)
+                .and_then(|color| minimessage::NAMED_COLORS.iter()
+                    .position(|named| *named == color));
+            if let Some(index) = color {
+                text.push('\u{00A7}');
+                text.push(std::char::from_digit(index as u32, 16)
+                    .unwrap_or('f'));
+            }
+            for (key, code) in LEGACY_DECORATION_CODES {
+                if map.get(key).and_then(Value::as_bool) == Some(true) {
+                    text.push('\u{00A7}');
+                    text.push(code);
+                }
+            }
+            if let Some(string) = map.get("text").and_then(Value::as_str) {
+                text.push_str(string);
+            }
+            if let Some(extra) = map.get("extra") {
+                text.push_str(&legacy_text(extra));
+            }
+        },
+        _ => {},
+    }
+    text
+}
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_DOUBLE: u8 = 6;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+
+// Java's modified UTF-8: null is two bytes and supplementary characters are
+// written as surrogate pairs
+fn write_nbt_string(data: &mut Vec<u8>, string: &str) {
+    let mut bytes: Vec<u8> = Vec::new();
+    for unit in string.encode_utf16() {
+        match unit {
+            0x0001..=0x007F => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                bytes.push(0xC0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            },
+            _ => {
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            },
+        }
+    }
+    data.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    data.extend_from_slice(&bytes);
+}
+
+fn nbt_tag(value: &Value) -> u8 {
+    match value {
+        Value::Bool(_) => TAG_BYTE,
+        Value::Number(number) if number.is_f64() => TAG_DOUBLE,
+        Value::Number(number) => match number.as_i64() {
+            Some(n) if n >= i32::MIN as i64 && n <= i32::MAX as i64 => TAG_INT,
+            _ => TAG_LONG,
+        },
+        Value::String(_) => TAG_STRING,
+        Value::Array(_) => TAG_LIST,
+        Value::Object(_) | Value::Null => TAG_COMPOUND,
+    }
+}
+
+fn write_nbt_payload(data: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Bool(b) => data.push(*b as u8),
+        Value::Number(number) => match nbt_tag(value) {
+            TAG_DOUBLE => data.extend_from_slice(
+                &number.as_f64().unwrap_or_default().to_be_bytes()),
+            TAG_INT => data.extend_from_slice(
+                &(number.as_i64().unwrap_or_default() as i32).to_be_bytes()),
+            _ => data.extend_from_slice(
+                &number.as_i64().unwrap_or_default().to_be_bytes()),
+        },
+        Value::String(string) => write_nbt_string(data, string),
+        Value::Array(values) => {
+            // Lists must hold a single tag type, so mixed component lists
+            // are written as compounds
+            let tag = match values.first() {
+                None => TAG_END,
+                Some(first) if values.iter()
+                    .all(|value| nbt_tag(value) == nbt_tag(first)) =>
+                        nbt_tag(first),
+                Some(_) => TAG_COMPOUND,
+            };
+            data.push(tag);
+            data.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                match (tag, value) {
+                    (TAG_COMPOUND, Value::String(string)) =>
+                        write_nbt_payload(data, &json!({"text": string})),
+                    (TAG_COMPOUND, Value::Object(_)) =>
+                        write_nbt_payload(data, value),
+                    (TAG_COMPOUND, _) => write_nbt_payload(
+                        data, &json!({"text": value.to_string()})),
+                    _ => write_nbt_payload(data, value),
+                }
+            }
+        },
+        Value::Object(map) => {
+            for (key, value) in map {
+                if value.is_null() {
+                    continue;
+                }
+                data.push(nbt_tag(value));
+                write_nbt_string(data, key);
+                write_nbt_payload(data, value);
+            }
+            data.push(TAG_END);
+        },
+        Value::Null => data.push(TAG_END),
+    }
+}
+
+// Network NBT has no name on the root tag
+pub fn write_nbt_component(data: &mut Vec<u8>, component: &Value) {
+    match component {
+        Value::String(string) => {
+            data.push(TAG_STRING);
+            write_nbt_string(data, string);
+        },
+        Value::Object(_) => {
+            data.push(TAG_COMPOUND);
+            write_nbt_payload(data, component);
+        },
+        Value::Array(_) => {
+            data.push(TAG_COMPOUND);
+            write_nbt_payload(data, &json!({"text": "", "extra": component}));
+        },
+        _ => {
+            data.push(TAG_STRING);
+            write_nbt_string(data, &component.to_string());
+        },
+    }
+}
@@ -1,10 +1,11 @@
 // Yeahbut May 2024
 
-use std::mem;
-use tokio::net::{TcpStream, tcp::{OwnedReadHalf, OwnedWriteHalf}};
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+use serde_json::json;
 
 use purple_cello_mc_protocol::{
-    mc_types::{self, Packet, ProtocolConnection},
+    mc_types::{Packet, ProtocolConnection},
     handshake,
     login,
 };
@@ -13,13 +14,52 @@ use crate::status_handle;
 use crate::login_handle;
 use crate::listener;
 use crate::info_messages;
+use crate::wake;
+use crate::legacy_ping;
+use crate::forge;
+use crate::proxy_protocol;
+use crate::versions;
+use crate::play_handle::{self, BackendLink};
+
+// Handshake intent of a client sent here by a Transfer packet (1.20.5+)
+const TRANSFER_INTENT: i32 = 3;
+
+#[derive(Debug, PartialEq)]
+enum LoginIntent {
+    Login,
+    Transferred,
+    // The listener does not accept transfers
+    TransferRefused,
+}
+
+// None if the handshake is not for a login
+fn login_intent(
+    next_state: i32,
+    accepts_transfers: bool,
+) -> Option<LoginIntent> {
+    match next_state {
+        2 => Some(LoginIntent::Login),
+        TRANSFER_INTENT if accepts_transfers => Some(LoginIntent::Transferred),
+        TRANSFER_INTENT => Some(LoginIntent::TransferRefused),
+        _ => None,
+    }
+}
 
 pub async fn handle_client(
-    client_socket: TcpStream,
+    mut client_socket: TcpStream,
+    peer_addr: SocketAddr,
     mut proxy_info: listener::ProxyInfo,
 ) {
-    println!("Accepting Connection");
-    let backend_addr = proxy_info.formatted_backend_address();
+    // Behind a load balancer the peer is the balancer, not the player
+    let client_addr = match proxy_protocol::accept(
+        &proxy_info.proxy_protocol, &mut client_socket, peer_addr).await {
+            Ok(client_addr) => client_addr,
+            Err(err) => {
+                println!("Bad PROXY header from {}: {}", peer_addr, err);
+                return;
+            },
+    };
+    println!("Accepting Connection from {}", client_addr);
 
     let (mut client_reader, mut client_writer) = client_socket.into_split();
     let mut client_conn = ProtocolConnection::new(
@@ -27,62 +67,97 @@ pub async fn handle_client(
         &mut client_writer,
     );
 
-    let mut backend_socket: (OwnedReadHalf, OwnedWriteHalf);
-    let mut server_conn: Option<ProtocolConnection<'_>> =
-        match TcpStream::connect(backend_addr).await {
-            Ok(backend_stream) => {
-                backend_socket = backend_stream.into_split();
-                Some(ProtocolConnection::new(
-                    &mut backend_socket.0, &mut backend_socket.1))
-            },
-            Err(_) => None,
-    };
-
     let mut buffer: [u8; 1] = [0; 1];
     client_conn.stream_read.peek(&mut buffer)
         .await.expect("Failed to peek at first byte from stream");
     let packet_id: u8 = buffer[0];
 
-    if packet_id == 0xFE {
-        status_handle::respond_legacy_status(&mut client_conn)
-            .await.expect("Error handling legacy status request");
+    if packet_id == legacy_ping::LEGACY_PING {
+        status_handle::respond_legacy_status(
+            proxy_info,
+            client_addr,
+            &mut client_conn,
+        ).await.expect("Error handling legacy status request");
         return;
     } else {
         let handshake_packet =
             handshake::serverbound::Handshake::read(&mut client_conn)
                 .await.expect("Error reading handshake packet");
         println!("Next state: {}", handshake_packet.next_state);
+        let intent = login_intent(
+            handshake_packet.next_state, proxy_info.accepts_transfers);
+        let transferred = intent == Some(LoginIntent::Transferred);
         if handshake_packet.next_state == 1 {
             println!("Receiving Status Request");
             status_handle::respond_status(
                 proxy_info,
+                client_addr,
+                handshake_packet.protocol_version,
                 &mut client_conn,
-                &mut server_conn,
             ).await.expect("Error handling status request");
             return;
-        } else if handshake_packet.next_state == 2 {
-            if handshake_packet.protocol_version == mc_types::VERSION_PROTOCOL {
-                match server_conn {
-                    Some(mut server_conn) => {
-                        if login_handle::respond_login(
+        } else if intent == Some(LoginIntent::TransferRefused) {
+            println!("Refusing transferred login from {}", client_addr);
+            login::clientbound::Disconnect {
+                reason: json!({
+                    "text": info_messages::TRANSFERS_DISABLED_DISCONNECT,
+                }).to_string()
+            }
+                .write(&mut client_conn).await.expect(
+                    "Error sending disconnect on: Transfers disabled");
+        } else if intent.is_some() {
+            if transferred {
+                println!("Transferred login from {}", client_addr);
+            }
+            let protocol = handshake_packet.protocol_version;
+            let forge_marker =
+                forge::handshake_marker(&handshake_packet.server_address);
+            if let Some(marker) = &forge_marker {
+                println!("Modded client handshake: {:?}", marker);
+            }
+            let accepted = proxy_info.backend.protocols.supported();
+            if accepted.accepts(protocol) {
+                let mut backend: Option<BackendLink> =
+                    BackendLink::connect(&proxy_info, client_addr)
+                        .await.ok();
+                if backend.is_none() &&
+                    wake::wait_for_backend(&proxy_info).await {
+                        backend = BackendLink::connect(
+                            &proxy_info, client_addr).await.ok();
+                }
+                match backend {
+                    Some(mut backend) => {
+                        match login_handle::respond_login(
                             &mut proxy_info,
+                            protocol,
+                            client_addr,
+                            transferred,
+                            forge_marker.as_deref(),
                             &mut client_conn,
-                            &mut server_conn,
+                            &mut backend,
                         ).await.expect(
                             "Error logging into proxy or server"
                         ) {
-                            handle_play(
+                            Some(player) => play_handle::handle_play(
+                                proxy_info,
+                                player,
+                                protocol,
+                                client_addr,
+                                forge_marker,
                                 client_conn,
-                                server_conn,
-                            ).await;
-                        } else {
-                            println!("Player blocked from server");
+                                backend,
+                            ).await,
+                            None => println!("Player blocked from server"),
                         }
                     }
                     None => {
+                        let reason = if wake::is_starting(&proxy_info) {
+                            info_messages::BACKEND_STARTING_DISCONNECT
+                        } else {
+                            info_messages::BACKEND_DOWN_DISCONNECT
+                        };
                         login::clientbound::Disconnect {
-                            reason: info_messages::BACKEND_DOWN_DISCONNECT
-                                .to_string()
+                            reason: reason.to_string()
                         }
                             .write(&mut client_conn)
                             .await
@@ -92,25 +167,19 @@ Failed to connect to the backend server");
                     }
                 };
             }
-            else
-            if handshake_packet.protocol_version < mc_types::VERSION_PROTOCOL {
-                println!("Client on outdated version");
-                login::clientbound::Disconnect {
-                    reason: format!(
-                        "\"Client Error: Outdated Version (I'm on {})\"",
-                        mc_types::VERSION_NAME,
-                    ).to_string()
-                }
-                    .write(&mut client_conn).await.expect(
-                        "Error sending disconnect on: Client on wrong version");
-            // if handshake_packet.protocol_version > mc_types::VERSION_PROTOCOL
-            } else {
-                println!("Client on future version");
+            else {
+                let template = if protocol > accepted.newest() {
+                    println!("Client on future version");
+                    &proxy_info.version_messages.future
+                } else {
+                    println!("Client on outdated version");
+                    &proxy_info.version_messages.outdated
+                };
                 login::clientbound::Disconnect {
-                    reason: format!(
-                        "\"Client Error: Future Version (I'm on {})\"",
-                        mc_types::VERSION_NAME,
-                    ).to_string()
+                    reason: json!({
+                        "text": versions::fill_template(
+                            template, protocol, &accepted),
+                    }).to_string()
                 }
                     .write(&mut client_conn).await.expect(
                         "Error sending disconnect on: Client on wrong version");
@@ -124,34 +193,17 @@ Failed to connect to the backend server");
     println!("Connection Closed");
 }
 
-async fn handle_play<'a>(
-    mut client_conn: ProtocolConnection<'a>,
-    mut server_conn: ProtocolConnection<'a>,
-) {
-    let client_conn: &mut ProtocolConnection<'static> =
-        unsafe { mem::transmute(&mut client_conn) };
-    let server_conn: &mut ProtocolConnection<'static> =
-        unsafe { mem::transmute(&mut server_conn) };
-
-    let (mut client_write_conn, mut client_read_conn) =
-        client_conn.split_conn().expect(
-            "Error copying from client to backend");
-    let (mut server_write_conn, mut server_read_conn) =
-        server_conn.split_conn().expect(
-            "Error copying from backend to client");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Forward from client to backend
-    let to_backend = tokio::spawn(async move {
-        client_read_conn.forward_play(&mut server_write_conn).await.expect(
-            "Error copying from client to backend");
-    });
-
-    // Forward from backend to client
-    let to_client = tokio::spawn(async move {
-        server_read_conn.forward_play(&mut client_write_conn).await.expect(
-            "Error copying from backend to client");
-    });
-
-    tokio::try_join!(to_backend, to_client).expect(
-        "Error copying between the client and backend");
+    #[test]
+    fn transfers_need_opting_in() {
+        assert_eq!(login_intent(1, true), None);
+        assert_eq!(login_intent(2, false), Some(LoginIntent::Login));
+        assert_eq!(login_intent(TRANSFER_INTENT, false),
+            Some(LoginIntent::TransferRefused));
+        assert_eq!(login_intent(TRANSFER_INTENT, true),
+            Some(LoginIntent::Transferred));
+    }
 }
@@ -0,0 +1,190 @@
+// Yeahbut October 2026
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::chat;
+use crate::listener;
+use crate::packet_ids;
+use crate::raw_packet;
+use crate::sessions::{self, TransferOutcome};
+use crate::whitelist::Player;
+
+// Group used for players whose whitelist entry has none
+pub const DEFAULT_GROUP: &str = "default";
+
+#[derive(Clone)]
+pub struct CommandConfig {
+    // Commands handled by the proxy instead of the backend, without the slash
+    pub enabled: Vec<String>,
+    // Groups allowed to run a command; commands not listed are open to all
+    pub permissions: HashMap<String, Vec<String>>,
+}
+
+impl CommandConfig {
+    pub fn is_allowed(&self, command: &str, player: &Player) -> bool {
+        let groups = match self.permissions.get(command) {
+            Some(groups) => groups,
+            None => return true,
+        };
+        let group = player.group.as_deref().unwrap_or(DEFAULT_GROUP);
+        groups.iter().any(|allowed| allowed == group)
+    }
+}
+
+pub enum CommandAction {
+    Reply(Value),
+    Switch(String),
+}
+
+// Returns the command line if the packet is a chat command the proxy handles
+pub fn intercept(
+    config: &CommandConfig,
+    protocol: i32,
+    data: &[u8],
+) -> Option<String> {
+    let mut index = 0;
+    let packet_id = raw_packet::read_var_int(data, &mut index)?;
+    if !packet_ids::play::serverbound::chat_command(protocol)
+        .contains(&packet_id) {
+            return None;
+    }
+
+    let command_line = raw_packet::read_string(data, &mut index)?;
+    let command = command_line.split_whitespace().next()?.to_lowercase();
+    if config.enabled.iter().any(|enabled| *enabled == command) {
+        Some(command_line)
+    } else {
+        None
+    }
+}
+
+// Argument signatures are always this long
+const SIGNATURE_SIZE: usize = 256;
+
+// A signed command carries the client's last seen message count, which the
+// backend expects to hear about. When the proxy swallows the command this
+// builds the Message Acknowledgement to send in its place.
+pub fn dropped_acknowledgement(protocol: i32, data: &[u8]) -> Option<Vec<u8>> {
+    let mut index = 0;
+    let packet_id = raw_packet::read_var_int(data, &mut index)?;
+    if packet_ids::play::serverbound::signed_chat_command(protocol)?
+        != packet_id {
+            return None;
+    }
+
+    raw_packet::read_string(data, &mut index)?;
+    // Timestamp and salt
+    index += 16;
+    let signatures = raw_packet::read_var_int(data, &mut index)?;
+    for _ in 0..signatures {
+        raw_packet::read_string(data, &mut index)?;
+        index += SIGNATURE_SIZE;
+    }
+    let message_count = raw_packet::read_var_int(data, &mut index)?;
+    if message_count <= 0 {
+        return None;
+    }
+
+    let mut packet = raw_packet::new_packet(
+        packet_ids::play::serverbound::message_acknowledgement(protocol)?);
+    raw_packet::write_var_int(&mut packet, message_count);
+    Some(packet)
+}
+
+fn reply(message: &str) -> CommandAction {
+    CommandAction::Reply(chat::text(message, "light_purple"))
+}
+
+fn error(message: &str) -> CommandAction {
+    CommandAction::Reply(chat::text(message, "red"))
+}
+
+pub fn run(
+    proxy_info: &listener::ProxyInfo,
+    player: &Player,
+    command_line: &str,
+) -> Vec<CommandAction> {
+    let mut words = command_line.split_whitespace();
+    let command = match words.next() {
+        Some(command) => command.to_lowercase(),
+        None => return Vec::new(),
+    };
+    let args: Vec<&str> = words.collect();
+
+    if !proxy_info.commands.is_allowed(&command, player) {
+        return vec![error(&format!(
+            "You do not have permission to use /{}", command))];
+    }
+
+    println!("{} ran proxy command: /{}", player.name, command_line);
+
+    match command.as_str() {
+        "proxy" => vec![
+            reply(&format!(
+                "Purple Cello Reverse Proxy v{}", env!("CARGO_PKG_VERSION"))),
+            reply(&format!(
+                "You are connected to {}", proxy_info.backend.name)),
+        ],
+        "server" => match args.first() {
+            Some(target) => vec![CommandAction::Switch(target.to_string())],
+            None => {
+                let names: Vec<&str> = proxy_info.backends.iter()
+                    .map(|backend| backend.name.as_str())
+                    .collect();
+                vec![
+                    reply(&format!(
+                        "You are connected to {}", proxy_info.backend.name)),
+                    reply(&format!("Servers: {}", names.join(", "))),
+                ]
+            },
+        },
+        "list" => {
+            let names: Vec<String> = sessions::online_players().into_iter()
+                .filter(|session| session.backend == proxy_info.backend.name)
+                .map(|session| session.name)
+                .collect();
+            vec![reply(&format!("{} online on {}: {}",
+                names.len(), proxy_info.backend.name, names.join(", ")))]
+        },
+        "glist" => {
+            let players = sessions::online_players();
+            let mut lines = vec![
+                reply(&format!("{} online in total", players.len()))];
+            for backend in &proxy_info.backends {
+                let names: Vec<&str> = players.iter()
+                    .filter(|session| session.backend == backend.name)
+                    .map(|session| session.name.as_str())
+                    .collect();
+                lines.push(reply(&format!("[{}] ({}): {}",
+                    backend.name, names.len(), names.join(", "))));
+            }
+            lines
+        },
+        "transfer" => {
+            let address = args.get(1)
+                .and_then(|address| sessions::parse_address(address));
+            match (args.first(), address) {
+                (Some(&"all"), Some((host, port))) => {
+                    let (sent, skipped) = sessions::transfer_all(&host, port);
+                    vec![reply(&format!("Transferring {} player(s) to {}:{}, \
+skipped {} on clients older than 1.20.5", sent, host, port, skipped))]
+                },
+                (Some(name), Some((host, port))) =>
+                    match sessions::transfer(name, &host, port) {
+                        TransferOutcome::Sent => vec![reply(&format!(
+                            "Transferring {} to {}:{}", name, host, port))],
+                        TransferOutcome::ClientTooOld => vec![error(&format!(
+                            "Can't transfer {}, their client is too old",
+                            name))],
+                        TransferOutcome::NotConnected => vec![error(
+                            &format!("{} is not connected", name))],
+                    },
+                _ => vec![error(
+                    "Usage: /transfer <player|all> <host[:port]>")],
+            }
+        },
+        _ => vec![error(&format!("Unknown proxy command: /{}", command))],
+    }
+}
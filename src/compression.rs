@@ -0,0 +1,134 @@
+// Yeahbut October 2026
+
+// Packet compression, negotiated separately with the client and with each
+// backend. Frames are what ProtocolConnection reads and writes, the bytes
+// after the length prefix. Once compression is on, a frame starts with the
+// uncompressed length of the packet, or 0 if the packet was sent as is.
+
+use std::io::{self, Read, Write};
+
+use flate2::{Compression as Level, read::ZlibDecoder, write::ZlibEncoder};
+
+use purple_cello_mc_protocol::mc_types::{
+    self,
+    ProtocolWrite,
+    WriteHaftProtocolConnection,
+};
+
+use crate::raw_packet;
+
+// The vanilla server refuses packets that claim to be larger than this
+const MAX_PACKET_SIZE: i32 = 8 * 1024 * 1024;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Turns a frame into the packet id and body
+pub fn decode(threshold: Option<i32>, frame: &[u8]) -> io::Result<Vec<u8>> {
+    let threshold = match threshold {
+        Some(threshold) => threshold,
+        None => return Ok(frame.to_vec()),
+    };
+
+    let mut index = 0;
+    let length = raw_packet::read_var_int(frame, &mut index)
+        .ok_or_else(|| invalid("missing uncompressed length"))?;
+    if length == 0 {
+        return Ok(frame[index..].to_vec());
+    }
+    if length < threshold || length > MAX_PACKET_SIZE {
+        return Err(invalid("bad uncompressed length"));
+    }
+
+    let mut packet = Vec::with_capacity(length as usize);
+    ZlibDecoder::new(&frame[index..])
+        .take(length as u64)
+        .read_to_end(&mut packet)?;
+    if packet.len() != length as usize {
+        return Err(invalid("uncompressed length mismatch"));
+    }
+    Ok(packet)
+}
+
+// Turns a packet id and body into a frame
+pub fn encode(threshold: Option<i32>, packet: &[u8]) -> Vec<u8> {
+    let threshold = match threshold {
+        Some(threshold) => threshold,
+        None => return packet.to_vec(),
+    };
+
+    let mut frame = Vec::new();
+    if (packet.len() as i32) < threshold {
+        raw_packet::write_var_int(&mut frame, 0);
+        frame.extend_from_slice(packet);
+        return frame;
+    }
+
+    raw_packet::write_var_int(&mut frame, packet.len() as i32);
+    let mut encoder = ZlibEncoder::new(frame, Level::default());
+    // Writing to a Vec cannot fail
+    let _ = encoder.write_all(packet);
+    encoder.finish().unwrap_or_default()
+}
+
+// A packet along with the frame it arrived in, so it can be forwarded without
+// recompressing when both sides use the same threshold
+pub struct Frame {
+    pub packet: Vec<u8>,
+    raw: Vec<u8>,
+    compression: Option<i32>,
+}
+
+impl Frame {
+    pub fn read(compression: Option<i32>, raw: Vec<u8>) -> io::Result<Self> {
+        Ok(Self {
+            packet: decode(compression, &raw)?,
+            raw,
+            compression,
+        })
+    }
+}
+
+pub struct PacketWriter {
+    pub conn: WriteHaftProtocolConnection<'static>,
+    pub compression: Option<i32>,
+}
+
+impl PacketWriter {
+    pub async fn write(&mut self, packet: &[u8]) -> mc_types::Result<()> {
+        let mut frame = encode(self.compression, packet);
+        self.conn.write_data(&mut frame).await
+    }
+
+    pub async fn relay(&mut self, mut frame: Frame) -> mc_types::Result<()> {
+        if frame.compression == self.compression {
+            self.conn.write_data(&mut frame.raw).await
+        } else {
+            self.write(&frame.packet).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let small = raw_packet::new_packet(0x02);
+        let mut large = raw_packet::new_packet(0x02);
+        large.extend(std::iter::repeat(7).take(1000));
+
+        for packet in [&small, &large] {
+            for threshold in [None, Some(0), Some(256)] {
+                let frame = encode(threshold, packet);
+                assert_eq!(&decode(threshold, &frame).unwrap(), packet);
+            }
+        }
+
+        // Under the threshold the packet is sent as is after a 0 length
+        assert_eq!(encode(Some(256), &small), [0x00, 0x02]);
+        assert!(encode(Some(256), &large).len() < large.len());
+    }
+}
@@ -0,0 +1,421 @@
+// Yeahbut October 2026
+
+// Settings read once at startup from ./proxy.json. Every section is optional
+// and a missing or unreadable file leaves every feature off, with a single
+// backend on 127.0.0.1:25564. For example:
+//
+// {
+//     "backends": [
+//         {"name": "main", "addr": "127.0.0.1", "port": 25564,
+//             "protocols": {"oldest": 763, "newest": 767}},
+//         {"name": "creative", "addr": "127.0.0.1", "port": 25563,
+//             "protocols": [767], "proxy_protocol": "v2"}
+//     ],
+//     "listeners": {
+//         "offline": {"query_port": 25565},
+//         "online": {"proxy_protocol": ["10.0.0.0/8"]}
+//     },
+//     "wake": {
+//         "start_command": ["./start.sh"],
+//         "stop_command": ["./stop.sh"],
+//         "join_wait_seconds": 20,
+//         "idle_timeout_seconds": 900
+//     },
+//     "accepts_transfers": true,
+//     "session_cookies": {
+//         "secret": "<base64, the same on every proxy that should trust it>",
+//         "trust_window_seconds": 300
+//     }
+// }
+
+use std::fs;
+use std::time::Duration;
+use base64::{Engine as _, engine::general_purpose};
+use serde_json::Value;
+
+use purple_cello_mc_protocol::mc_types;
+
+use crate::listener::{Backend, ProtocolVersions};
+use crate::proxy_protocol::{
+    Cidr,
+    InboundProxyProtocol,
+    ProxyProtocolVersion,
+};
+use crate::session_cookie::CookieConfig;
+use crate::wake::WakeConfig;
+
+const CONFIG_PATH: &str = "./proxy.json";
+const DEFAULT_JOIN_WAIT: Duration = Duration::from_secs(20);
+// The vanilla client gives up on a silent login after 30 seconds
+const MAX_JOIN_WAIT: Duration = Duration::from_secs(25);
+const DEFAULT_TRUST_WINDOW: Duration = Duration::from_secs(300);
+// Shorter secrets are too easy to guess
+const MIN_SECRET_SIZE: usize = 16;
+
+// Settings for one of the proxy's listeners
+pub struct ListenerConfig {
+    // UDP port to answer GameSpy4 queries on
+    pub query_port: Option<u16>,
+    // Load balancers that send a PROXY header, by address block
+    pub proxy_protocol: Option<InboundProxyProtocol>,
+}
+
+pub struct ProxyConfig {
+    // The first backend is where players join
+    pub backends: Vec<Backend>,
+    pub offline: ListenerConfig,
+    pub online: ListenerConfig,
+    pub wake: Option<WakeConfig>,
+    // Off unless set, as on the vanilla server
+    pub accepts_transfers: bool,
+    pub session_cookies: Option<CookieConfig>,
+}
+
+fn string_list(value: &Value) -> Option<Vec<String>> {
+    value.as_array()?.iter()
+        .map(|item| item.as_str().map(|item| item.to_string()))
+        .collect()
+}
+
+fn seconds(value: &Value) -> Option<Duration> {
+    value.as_u64().map(Duration::from_secs)
+}
+
+fn default_backend() -> Backend {
+    Backend {
+        name: "main".to_string(),
+        addr: "127.0.0.1".to_string(),
+        port: 25564,
+        protocols: ProtocolVersions::Set(vec![mc_types::VERSION_PROTOCOL]),
+        proxy_protocol: None,
+    }
+}
+
+// A list of protocol versions, or an inclusive range for backends running
+// ViaVersion. The proxy's own version when unset.
+fn protocols(protocols: &Value) -> Option<ProtocolVersions> {
+    if protocols.is_null() {
+        return Some(ProtocolVersions::Set(vec![mc_types::VERSION_PROTOCOL]));
+    }
+    if let Some(protocols) = protocols.as_array() {
+        let protocols: Option<Vec<i32>> = protocols.iter()
+            .map(|protocol| protocol.as_i64()
+                .and_then(|protocol| i32::try_from(protocol).ok()))
+            .collect();
+        return protocols.map(ProtocolVersions::Set);
+    }
+    let bound = |key: &str| protocols[key].as_i64()
+        .and_then(|protocol| i32::try_from(protocol).ok());
+    match (bound("oldest"), bound("newest")) {
+        (Some(oldest), Some(newest)) if oldest <= newest =>
+            Some(ProtocolVersions::Range(oldest, newest)),
+        _ => None,
+    }
+}
+
+// The PROXY header version a backend expects, if any
+fn outbound_proxy_protocol(
+    version: &Value,
+) -> Option<Option<ProxyProtocolVersion>> {
+    if version.is_null() {
+        return Some(None);
+    }
+    match version.as_str()?.to_lowercase().as_str() {
+        "v1" => Some(Some(ProxyProtocolVersion::V1)),
+        "v2" => Some(Some(ProxyProtocolVersion::V2)),
+        _ => None,
+    }
+}
+
+fn backend(backend: &Value) -> Option<Backend> {
+    let name = backend["name"].as_str().filter(|name| !name.is_empty());
+    let (name, port) = match (name, port(&backend["port"])) {
+        (Some(name), Some(port)) => (name, port),
+        _ => {
+            println!("Ignoring backend without a name and port: {}", backend);
+            return None;
+        },
+    };
+    let protocols = match protocols(&backend["protocols"]) {
+        Some(protocols) => protocols,
+        None => {
+            println!("Ignoring backend {} with invalid protocols", name);
+            return None;
+        },
+    };
+    let proxy_protocol =
+        match outbound_proxy_protocol(&backend["proxy_protocol"]) {
+            Some(version) => version,
+            None => {
+                println!("Ignoring backend {}, proxy_protocol must be \"v1\" \
+or \"v2\"", name);
+                return None;
+            },
+        };
+    Some(Backend {
+        name: name.to_string(),
+        addr: backend["addr"].as_str().unwrap_or("127.0.0.1").to_string(),
+        port,
+        protocols,
+        proxy_protocol,
+    })
+}
+
+fn backends(backends: &Value) -> Vec<Backend> {
+    let mut parsed: Vec<Backend> = Vec::new();
+    for backend in backends.as_array().into_iter().flatten()
+        .filter_map(backend) {
+            if parsed.iter().any(|other| other.name == backend.name) {
+                println!("Ignoring duplicate backend {}", backend.name);
+                continue;
+            }
+            parsed.push(backend);
+    }
+    if parsed.is_empty() {
+        parsed.push(default_backend());
+    }
+    parsed
+}
+
+fn port(value: &Value) -> Option<u16> {
+    value.as_u64().and_then(|port| u16::try_from(port).ok())
+}
+
+fn inbound_proxy_protocol(trusted: &Value) -> Option<InboundProxyProtocol> {
+    let trusted: Vec<Cidr> = string_list(trusted)?.iter()
+        .filter_map(|cidr| {
+            let parsed = Cidr::parse(cidr);
+            if parsed.is_none() {
+                println!("Ignoring invalid proxy_protocol address {}", cidr);
+            }
+            parsed
+        })
+        .collect();
+    if trusted.is_empty() {
+        return None;
+    }
+    Some(InboundProxyProtocol { trusted })
+}
+
+fn listener_config(listener: &Value) -> ListenerConfig {
+    ListenerConfig {
+        query_port: port(&listener["query_port"]),
+        proxy_protocol: inbound_proxy_protocol(&listener["proxy_protocol"]),
+    }
+}
+
+fn wake_config(wake: &Value) -> Option<WakeConfig> {
+    let start_command = match string_list(&wake["start_command"]) {
+        Some(command) if !command.is_empty() => command,
+        _ => {
+            println!("Ignoring wake config without a start_command");
+            return None;
+        },
+    };
+    Some(WakeConfig {
+        start_command,
+        stop_command: string_list(&wake["stop_command"]).unwrap_or_default(),
+        join_wait: seconds(&wake["join_wait_seconds"])
+            .unwrap_or(DEFAULT_JOIN_WAIT)
+            .min(MAX_JOIN_WAIT),
+        idle_timeout: seconds(&wake["idle_timeout_seconds"]),
+    })
+}
+
+fn cookie_config(cookies: &Value) -> Option<CookieConfig> {
+    let secret = cookies["secret"].as_str()
+        .and_then(|secret| general_purpose::STANDARD.decode(secret).ok());
+    match secret {
+        Some(secret) if secret.len() >= MIN_SECRET_SIZE => Some(CookieConfig {
+            secret,
+            trust_window: seconds(&cookies["trust_window_seconds"])
+                .unwrap_or(DEFAULT_TRUST_WINDOW),
+        }),
+        _ => {
+            println!("Ignoring session_cookies, the secret must be base64 of \
+at least {} bytes", MIN_SECRET_SIZE);
+            None
+        },
+    }
+}
+
+pub fn parse(config: &Value) -> ProxyConfig {
+    ProxyConfig {
+        backends: backends(&config["backends"]),
+        offline: listener_config(&config["listeners"]["offline"]),
+        online: listener_config(&config["listeners"]["online"]),
+        wake: if config["wake"].is_object() {
+            wake_config(&config["wake"])
+        } else {
+            None
+        },
+        accepts_transfers: config["accepts_transfers"].as_bool()
+            .unwrap_or(false),
+        session_cookies: if config["session_cookies"].is_object() {
+            cookie_config(&config["session_cookies"])
+        } else {
+            None
+        },
+    }
+}
+
+pub fn load() -> ProxyConfig {
+    let config = fs::read_to_string(CONFIG_PATH).ok()
+        .and_then(|data| match serde_json::from_str(&data) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                println!("Ignoring {}: {}", CONFIG_PATH, e);
+                None
+            },
+        })
+        .unwrap_or(Value::Null);
+    parse(&config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn wake_section() {
+        let config = parse(&json!({
+            "wake": {
+                "start_command": ["./start.sh", "--nogui"],
+                "idle_timeout_seconds": 600,
+            },
+        }));
+        let wake = config.wake.unwrap();
+        assert_eq!(wake.start_command, ["./start.sh", "--nogui"]);
+        assert!(wake.stop_command.is_empty());
+        assert_eq!(wake.join_wait, DEFAULT_JOIN_WAIT);
+        assert_eq!(wake.idle_timeout, Some(Duration::from_secs(600)));
+
+        let config = parse(&json!({
+            "wake": {"start_command": ["./start.sh"], "join_wait_seconds": 90},
+        }));
+        assert_eq!(config.wake.unwrap().join_wait, MAX_JOIN_WAIT);
+
+        assert!(parse(&json!({"wake": {"stop_command": ["./stop.sh"]}}))
+            .wake.is_none());
+        assert!(parse(&json!([1, 2])).wake.is_none());
+    }
+
+    #[test]
+    fn backend_list() {
+        let config = parse(&json!({
+            "backends": [
+                {"name": "main", "port": 25564},
+                {"name": "creative", "addr": "10.0.0.2", "port": 25565},
+                {"name": "main", "port": 25566},
+                {"port": 25567},
+            ],
+        }));
+        let names: Vec<&str> = config.backends.iter()
+            .map(|backend| backend.name.as_str())
+            .collect();
+        assert_eq!(names, ["main", "creative"]);
+        assert_eq!(config.backends[1].formatted_address(), "10.0.0.2:25565");
+
+        let config = parse(&Value::Null);
+        assert_eq!(config.backends.len(), 1);
+        assert_eq!(config.backends[0].formatted_address(), "127.0.0.1:25564");
+    }
+
+    #[test]
+    fn backend_protocols() {
+        let config = parse(&json!({
+            "backends": [
+                {"name": "via", "port": 25564,
+                    "protocols": {"oldest": 762, "newest": 767}},
+                {"name": "pinned", "port": 25565, "protocols": [765, 767]},
+                {"name": "default", "port": 25566},
+                {"name": "backwards", "port": 25567,
+                    "protocols": {"oldest": 767, "newest": 762}},
+                {"name": "typo", "port": 25568, "protocols": ["767"]},
+            ],
+        }));
+        assert_eq!(config.backends.len(), 3);
+        let [via, pinned, default] = &config.backends[..] else {
+            unreachable!()
+        };
+        assert!(matches!(via.protocols, ProtocolVersions::Range(762, 767)));
+        assert!(pinned.protocols.accepts(765));
+        assert!(!pinned.protocols.accepts(766));
+        assert!(default.protocols.accepts(mc_types::VERSION_PROTOCOL));
+    }
+
+    #[test]
+    fn backend_proxy_protocol() {
+        let config = parse(&json!({
+            "backends": [
+                {"name": "v1", "port": 25564, "proxy_protocol": "v1"},
+                {"name": "v2", "port": 25565, "proxy_protocol": "V2"},
+                {"name": "plain", "port": 25566},
+                {"name": "v3", "port": 25567, "proxy_protocol": "v3"},
+            ],
+        }));
+        let versions: Vec<Option<ProxyProtocolVersion>> = config.backends
+            .iter()
+            .map(|backend| backend.proxy_protocol)
+            .collect();
+        assert_eq!(versions, [
+            Some(ProxyProtocolVersion::V1),
+            Some(ProxyProtocolVersion::V2),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn query_ports() {
+        let config = parse(&json!({
+            "listeners": {"offline": {"query_port": 25565}},
+        }));
+        assert_eq!(config.offline.query_port, Some(25565));
+        assert_eq!(config.online.query_port, None);
+        let config = parse(&json!({
+            "listeners": {"online": {"query_port": 70000}},
+        }));
+        assert_eq!(config.online.query_port, None);
+    }
+
+    #[test]
+    fn trusted_balancers() {
+        let config = parse(&json!({
+            "listeners": {
+                "online": {"proxy_protocol": ["10.0.0.0/8", "not an address"]},
+            },
+        }));
+        let inbound = config.online.proxy_protocol.unwrap();
+        assert_eq!(inbound.trusted, [Cidr::parse("10.0.0.0/8").unwrap()]);
+        assert!(inbound.is_trusted("10.1.2.3".parse().unwrap()));
+        assert!(config.offline.proxy_protocol.is_none());
+    }
+
+    #[test]
+    fn transfers_default_off() {
+        assert!(!parse(&Value::Null).accepts_transfers);
+        assert!(parse(&json!({"accepts_transfers": true})).accepts_transfers);
+    }
+
+    #[test]
+    fn cookie_secret() {
+        let config = parse(&json!({
+            "session_cookies": {
+                "secret": general_purpose::STANDARD.encode([7; 32]),
+                "trust_window_seconds": 120,
+            },
+        }));
+        let cookies = config.session_cookies.unwrap();
+        assert_eq!(cookies.secret, [7; 32]);
+        assert_eq!(cookies.trust_window, Duration::from_secs(120));
+
+        let short = general_purpose::STANDARD.encode([7; 8]);
+        assert!(parse(&json!({"session_cookies": {"secret": short}}))
+            .session_cookies.is_none());
+        assert!(parse(&json!({"session_cookies": {"secret": "not base64!"}}))
+            .session_cookies.is_none());
+        assert!(parse(&Value::Null).session_cookies.is_none());
+    }
+}
@@ -0,0 +1,125 @@
+// Yeahbut October 2026
+
+// Which protocol state each direction of a relayed connection is in. From
+// 1.20.2 (764) on, the client enters the configuration state after login and
+// can be sent back to it from play, e.g. to switch servers. Packet ids mean
+// different things in each state, so packets are only injected or
+// intercepted in the state they belong to.
+
+use crate::packet_ids::{self, login, configuration, play};
+use crate::raw_packet;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum State {
+    Login,
+    Configuration,
+    Play,
+}
+
+pub struct StateTracker {
+    protocol: i32,
+    // Packets from the client to the backend
+    pub serverbound: State,
+    // Packets from the backend to the client
+    pub clientbound: State,
+}
+
+impl StateTracker {
+    // Starts just after Login Success was sent to the client
+    pub fn new(protocol: i32) -> Self {
+        if packet_ids::has_configuration_state(protocol) {
+            Self {
+                protocol,
+                serverbound: State::Login,
+                clientbound: State::Configuration,
+            }
+        } else {
+            Self {
+                protocol,
+                serverbound: State::Play,
+                clientbound: State::Play,
+            }
+        }
+    }
+
+    pub fn in_play(&self) -> bool {
+        self.serverbound == State::Play && self.clientbound == State::Play
+    }
+
+    pub fn client_packet(&mut self, packet: &[u8]) {
+        let packet_id = match raw_packet::packet_id(packet) {
+            Some(packet_id) => Some(packet_id),
+            None => return,
+        };
+        self.serverbound = match self.serverbound {
+            State::Login if packet_id ==
+                Some(login::serverbound::LOGIN_ACKNOWLEDGED) =>
+                    State::Configuration,
+            State::Configuration if packet_id == configuration::serverbound::
+                acknowledge_finish_configuration(self.protocol) =>
+                    State::Play,
+            State::Play if packet_id ==
+                play::serverbound::acknowledge_configuration(self.protocol) =>
+                    State::Configuration,
+            state => state,
+        };
+    }
+
+    pub fn backend_packet(&mut self, packet: &[u8]) {
+        let packet_id = match raw_packet::packet_id(packet) {
+            Some(packet_id) => Some(packet_id),
+            None => return,
+        };
+        self.clientbound = match self.clientbound {
+            State::Configuration if packet_id == configuration::clientbound::
+                finish_configuration(self.protocol) =>
+                    State::Play,
+            State::Play if packet_id ==
+                play::clientbound::start_configuration(self.protocol) =>
+                    State::Configuration,
+            state => state,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login_configuration_play_and_back() {
+        let protocol = 767;
+        let mut tracker = StateTracker::new(protocol);
+        assert_eq!(tracker.serverbound, State::Login);
+        assert_eq!(tracker.clientbound, State::Configuration);
+
+        tracker.client_packet(&raw_packet::new_packet(
+            login::serverbound::LOGIN_ACKNOWLEDGED));
+        assert_eq!(tracker.serverbound, State::Configuration);
+
+        tracker.backend_packet(&raw_packet::new_packet(
+            configuration::clientbound::finish_configuration(protocol)
+                .unwrap()));
+        tracker.client_packet(&raw_packet::new_packet(
+            configuration::serverbound::acknowledge_finish_configuration(
+                protocol).unwrap()));
+        assert!(tracker.in_play());
+
+        // The same id means something else in play
+        tracker.client_packet(&raw_packet::new_packet(
+            login::serverbound::LOGIN_ACKNOWLEDGED));
+        assert!(tracker.in_play());
+
+        tracker.backend_packet(&raw_packet::new_packet(
+            play::clientbound::start_configuration(protocol).unwrap()));
+        tracker.client_packet(&raw_packet::new_packet(
+            play::serverbound::acknowledge_configuration(protocol).unwrap()));
+        assert_eq!(tracker.serverbound, State::Configuration);
+        assert_eq!(tracker.clientbound, State::Configuration);
+    }
+
+    #[test]
+    fn no_configuration_before_1_20_2() {
+        assert!(StateTracker::new(763).in_play());
+    }
+}
@@ -0,0 +1,135 @@
+// Yeahbut October 2026
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use base64::{Engine as _, engine::general_purpose};
+use rand::Rng;
+use lazy_static::lazy_static;
+
+const ICON_FILE: &str = "./icon.png";
+const ICON_DIRECTORY: &str = "./icons";
+// How often the icon directory is listed again
+const DIRECTORY_EXPIRATION: Duration = Duration::from_secs(60);
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const ICON_SIZE: u32 = 64;
+
+// Which icon from the icon directory to show
+pub enum IconSelection {
+    // A file in the icon directory, e.g. from a scheduled MOTD entry
+    Named(String),
+    // The same icon for the whole day, given as days since the epoch
+    Daily(i64),
+    Random,
+}
+
+struct CachedIcon {
+    modified: Option<SystemTime>,
+    data_uri: Option<String>,
+}
+
+struct CachedDirectory {
+    icons: Vec<PathBuf>,
+    timestamp: Instant,
+}
+
+lazy_static! {
+    static ref ICON_CACHE: Arc<Mutex<HashMap<PathBuf, CachedIcon>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+    static ref DIRECTORY_CACHE: Arc<Mutex<Option<CachedDirectory>>> =
+        Arc::new(Mutex::new(None));
+}
+
+// Server list icons must be 64x64 PNGs
+pub fn validate_png(data: &[u8]) -> Result<(), String> {
+    if data.len() < 24 || data[..8] != PNG_SIGNATURE {
+        return Err("not a PNG file".to_string());
+    }
+    if &data[12..16] != b"IHDR" {
+        return Err("missing IHDR chunk".to_string());
+    }
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+    if width != ICON_SIZE || height != ICON_SIZE {
+        return Err(format!("icon is {}x{}, expected {}x{}",
+            width, height, ICON_SIZE, ICON_SIZE));
+    }
+    Ok(())
+}
+
+fn load_icon(path: &Path) -> Option<String> {
+    let data = fs::read(path).ok()?;
+    if let Err(e) = validate_png(&data) {
+        println!("Ignoring icon {}: {}", path.display(), e);
+        return None;
+    }
+    Some(format!("data:image/png;base64,{}",
+        general_purpose::STANDARD.encode(data)))
+}
+
+// Re-reads the icon only when its modification time changes
+fn cached_icon(path: &Path) -> Option<String> {
+    let modified = fs::metadata(path).ok()?.modified().ok();
+
+    let mut cache = ICON_CACHE.lock().unwrap();
+    if let Some(cached) = cache.get(path) {
+        if cached.modified == modified && modified.is_some() {
+            return cached.data_uri.clone();
+        }
+    }
+
+    println!("Loading icon {}", path.display());
+    let data_uri = load_icon(path);
+    cache.insert(path.to_path_buf(), CachedIcon {
+        modified,
+        data_uri: data_uri.clone(),
+    });
+    data_uri
+}
+
+fn directory_icons() -> Vec<PathBuf> {
+    let mut cache = DIRECTORY_CACHE.lock().unwrap();
+
+    if let Some(cached) = cache.as_ref() {
+        if cached.timestamp.elapsed() < DIRECTORY_EXPIRATION {
+            return cached.icons.clone();
+        }
+    }
+
+    let mut icons: Vec<PathBuf> = match fs::read_dir(ICON_DIRECTORY) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension()
+                .map_or(false, |extension| extension == "png"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    icons.sort();
+
+    *cache = Some(CachedDirectory {
+        icons: icons.clone(),
+        timestamp: Instant::now(),
+    });
+    icons
+}
+
+pub fn favicon(selection: IconSelection) -> Option<String> {
+    let icons = directory_icons();
+
+    let chosen = match selection {
+        IconSelection::Named(name) => Some(Path::new(ICON_DIRECTORY).join(name)),
+        _ if icons.is_empty() => None,
+        IconSelection::Daily(day) =>
+            Some(icons[day.rem_euclid(icons.len() as i64) as usize].clone()),
+        IconSelection::Random =>
+            Some(icons[rand::thread_rng().gen_range(0..icons.len())].clone()),
+    };
+
+    chosen.and_then(|path| cached_icon(&path))
+        .or_else(|| cached_icon(Path::new(ICON_FILE)))
+}
@@ -0,0 +1,38 @@
+// Yeahbut October 2026
+
+// Forge clients mark their handshake by appending e.g. "\0FML3\0" to the
+// server address, and Forge servers then negotiate mods with Login Plugin
+// Requests before Login Success. NeoForge on 1.20.2+ negotiates during the
+// configuration state instead, which is relayed like any other traffic.
+
+// Forge's login handshake runs on these channels
+const LOGIN_CHANNEL_NAMESPACES: [&str; 2] = ["fml:", "forge:"];
+
+// Everything from the first null in the handshake's server address
+pub fn handshake_marker(server_address: &str) -> Option<String> {
+    server_address.find('\0')
+        .map(|index| server_address[index..].to_string())
+}
+
+// Whether a backend's login plugin request is for the client's mod loader
+pub fn is_login_channel(channel: &str) -> bool {
+    LOGIN_CHANNEL_NAMESPACES.iter()
+        .any(|namespace| channel.starts_with(namespace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers() {
+        assert_eq!(handshake_marker("play.example.com\0FML3\0"),
+            Some("\0FML3\0".to_string()));
+        assert_eq!(handshake_marker("localhost\0FML2\0"),
+            Some("\0FML2\0".to_string()));
+        assert_eq!(handshake_marker("play.example.com"), None);
+
+        assert!(is_login_channel("fml:loginwrapper"));
+        assert!(!is_login_channel("velocity:player_info"));
+    }
+}
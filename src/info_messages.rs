@@ -9,6 +9,33 @@ pub const BACKEND_DOWN_DISCONNECT: &str = "\
 Please contact the admins if the issue persists:\n\
 purplecelloserver@gmail.com\"";
 
+pub const BACKEND_STARTING_PING: &str = "\
+Server is starting, please wait\n\
+Purple Cello Server";
+
+pub const BACKEND_STARTING_DISCONNECT: &str = "\
+\"Server is starting, please wait\n\
+Try joining again in a minute\"";
+
+pub const BACKENDS_DEGRADED_SAMPLE: &str =
+    "\u{00A7}eSome servers are currently offline";
+
+pub const BACKEND_MISCONFIGURED_DISCONNECT: &str = "\
+Server Error (The server is misconfigured)\n\
+Please contact the admins if the issue persists:\n\
+purplecelloserver@gmail.com";
+
+pub const TRANSFERS_DISABLED_DISCONNECT: &str =
+    "This server does not accept transfers";
+
+pub const OUTDATED_VERSION_DISCONNECT: &str = "\
+Client Error: Outdated Version\n\
+You are on {client_version}, please join with {supported}";
+
+pub const FUTURE_VERSION_DISCONNECT: &str = "\
+Client Error: Future Version\n\
+You are on {client_version}, please join with {supported}";
+
 pub const UUID_MISSING_DISCONNECT: &str = "Invalid UUID! (UUID Missing)";
 
 pub const WHITELIST_STATUS_INACTIVE_DISCONNECT: &str = "\
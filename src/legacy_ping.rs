@@ -0,0 +1,241 @@
+// Yeahbut October 2026
+
+// Server list pings from clients older than 1.7, which open with 0xFE instead
+// of a handshake. Every variant is answered with a 0xFF kick packet holding a
+// UTF-16BE string.
+
+pub const LEGACY_PING: u8 = 0xFE;
+const PING_PAYLOAD: u8 = 0x01;
+const PLUGIN_MESSAGE: u8 = 0xFA;
+const KICK: u8 = 0xFF;
+const PING_HOST_CHANNEL: &str = "MC|PingHost";
+
+#[derive(Debug, PartialEq)]
+pub enum LegacyPing {
+    // Beta 1.8 to 1.3 send 0xFE alone
+    Beta,
+    // 1.4 and 1.5 send 0xFE 0x01
+    V1_4,
+    // 1.6 follows 0xFE 0x01 with an MC|PingHost plugin message
+    V1_6 { protocol: u8, host: String, port: i32 },
+}
+
+pub struct LegacyStatus {
+    pub protocol: i32,
+    pub version: String,
+    pub motd: String,
+    pub online: i32,
+    pub max: i32,
+}
+
+fn read_u16(data: &[u8], index: &mut usize) -> Option<u16> {
+    let bytes = data.get(*index..*index + 2)?;
+    *index += 2;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_utf16(data: &[u8], index: &mut usize) -> Option<String> {
+    let length = read_u16(data, index)? as usize;
+    let units: Option<Vec<u16>> = (0..length)
+        .map(|_| read_u16(data, index))
+        .collect();
+    String::from_utf16(&units?).ok()
+}
+
+fn parse_ping_host(data: &[u8]) -> Option<LegacyPing> {
+    let mut index = 0;
+    if *data.get(index)? != PLUGIN_MESSAGE {
+        return None;
+    }
+    index += 1;
+    if read_utf16(data, &mut index)? != PING_HOST_CHANNEL {
+        return None;
+    }
+    let _length = read_u16(data, &mut index)?;
+    let protocol = *data.get(index)?;
+    index += 1;
+    let host = read_utf16(data, &mut index)?;
+    let port = data.get(index..index + 4)?;
+    let port = i32::from_be_bytes([port[0], port[1], port[2], port[3]]);
+    Some(LegacyPing::V1_6 { protocol, host, port })
+}
+
+// Takes everything the client sent, starting with 0xFE
+pub fn parse(data: &[u8]) -> LegacyPing {
+    if data.get(1) != Some(&PING_PAYLOAD) {
+        return LegacyPing::Beta;
+    }
+    // An unreadable MC|PingHost still gets the 1.4 reply
+    parse_ping_host(&data[2..]).unwrap_or(LegacyPing::V1_4)
+}
+
+// Whether there is no point waiting for more of the request
+pub fn is_complete(data: &[u8]) -> bool {
+    match data.get(1) {
+        Some(&PING_PAYLOAD) => matches!(parse(data), LegacyPing::V1_6 { .. }),
+        Some(_) => true,
+        None => false,
+    }
+}
+
+pub fn response(ping: &LegacyPing, status: &LegacyStatus) -> Vec<u8> {
+    let text = match ping {
+        // Beta clients split on the section sign, so the MOTD cannot be
+        // formatted
+        LegacyPing::Beta => format!("{}\u{00A7}{}\u{00A7}{}",
+            strip_formatting(&status.motd), status.online, status.max),
+        LegacyPing::V1_4 | LegacyPing::V1_6 { .. } =>
+            format!("\u{00A7}1\0{}\0{}\0{}\0{}\0{}",
+                status.protocol, status.version, status.motd,
+                status.online, status.max),
+    };
+
+    let units: Vec<u16> = text.encode_utf16().collect();
+    let mut data = vec![KICK];
+    data.extend_from_slice(&(units.len() as u16).to_be_bytes());
+    for unit in units {
+        data.extend_from_slice(&unit.to_be_bytes());
+    }
+    data
+}
+
+fn strip_formatting(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        if character == '\u{00A7}' {
+            characters.next();
+        } else {
+            stripped.push(character);
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> LegacyStatus {
+        LegacyStatus {
+            protocol: 767,
+            version: "1.21".to_string(),
+            motd: "\u{00A7}6Hi".to_string(),
+            online: 3,
+            max: 20,
+        }
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        let units: Vec<u16> = text.encode_utf16().collect();
+        let mut data = (units.len() as u16).to_be_bytes().to_vec();
+        for unit in units {
+            data.extend_from_slice(&unit.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn beta_ping() {
+        let ping = parse(&[0xFE]);
+        assert_eq!(ping, LegacyPing::Beta);
+        assert!(!is_complete(&[0xFE]));
+
+        let expected: Vec<u8> = vec![
+            0xFF, 0x00, 0x07,
+            0x00, b'H', 0x00, b'i',
+            0x00, 0xA7, 0x00, b'3',
+            0x00, 0xA7, 0x00, b'2', 0x00, b'0',
+        ];
+        assert_eq!(response(&ping, &status()), expected);
+    }
+
+    #[test]
+    fn ping_1_4() {
+        let ping = parse(&[0xFE, 0x01]);
+        assert_eq!(ping, LegacyPing::V1_4);
+        assert!(!is_complete(&[0xFE, 0x01]));
+
+        let mut expected: Vec<u8> = vec![0xFF, 0x00, 0x15];
+        for unit in "\u{00A7}1\0767\01.21\0\u{00A7}6Hi\03\020".encode_utf16() {
+            expected.extend_from_slice(&unit.to_be_bytes());
+        }
+        assert_eq!(expected.len(), 3 + 0x15 * 2);
+        assert_eq!(&expected[3..9], &[0x00, 0xA7, 0x00, b'1', 0x00, 0x00]);
+        assert_eq!(response(&ping, &status()), expected);
+    }
+
+    #[test]
+    fn ping_1_6() {
+        let host = utf16("localhost");
+        let mut request = vec![0xFE, 0x01, 0xFA];
+        request.extend(utf16(PING_HOST_CHANNEL));
+        request.extend_from_slice(&(1 + host.len() as u16 + 4).to_be_bytes());
+        request.push(78);
+        request.extend(host);
+        request.extend_from_slice(&25565i32.to_be_bytes());
+
+        assert!(is_complete(&request));
+        assert!(!is_complete(&request[..request.len() - 1]));
+        let ping = parse(&request);
+        assert_eq!(ping, LegacyPing::V1_6 {
+            protocol: 78,
+            host: "localhost".to_string(),
+            port: 25565,
+        });
+
+        // Same layout as the 1.4 reply
+        assert_eq!(response(&ping, &status()),
+            response(&LegacyPing::V1_4, &status()));
+    }
+
+    // A 1.6.2 client pinging localhost:25565, byte for byte
+    #[test]
+    fn ping_1_6_bytes() {
+        let request: [u8; 54] = [
+            0xFE, 0x01, 0xFA,
+            // "MC|PingHost"
+            0x00, 0x0B,
+            0x00, 0x4D, 0x00, 0x43, 0x00, 0x7C, 0x00, 0x50, 0x00, 0x69,
+            0x00, 0x6E, 0x00, 0x67, 0x00, 0x48, 0x00, 0x6F, 0x00, 0x73,
+            0x00, 0x74,
+            // Length of the rest
+            0x00, 0x19,
+            // Protocol 74
+            0x4A,
+            // "localhost"
+            0x00, 0x09,
+            0x00, 0x6C, 0x00, 0x6F, 0x00, 0x63, 0x00, 0x61, 0x00, 0x6C,
+            0x00, 0x68, 0x00, 0x6F, 0x00, 0x73, 0x00, 0x74,
+            // Port 25565
+            0x00, 0x00, 0x63, 0xDD,
+        ];
+        assert!(is_complete(&request));
+        let ping = parse(&request);
+        assert_eq!(ping, LegacyPing::V1_6 {
+            protocol: 74,
+            host: "localhost".to_string(),
+            port: 25565,
+        });
+
+        let status = LegacyStatus {
+            protocol: 74,
+            version: "1.6.2".to_string(),
+            motd: "A".to_string(),
+            online: 1,
+            max: 20,
+        };
+        // "\u{00A7}1\074\01.6.2\0A\01\020"
+        let expected: [u8; 39] = [
+            0xFF, 0x00, 0x12,
+            0x00, 0xA7, 0x00, 0x31, 0x00, 0x00,
+            0x00, 0x37, 0x00, 0x34, 0x00, 0x00,
+            0x00, 0x31, 0x00, 0x2E, 0x00, 0x36, 0x00, 0x2E, 0x00, 0x32,
+            0x00, 0x00,
+            0x00, 0x41, 0x00, 0x00,
+            0x00, 0x31, 0x00, 0x00,
+            0x00, 0x32, 0x00, 0x30,
+        ];
+        assert_eq!(response(&ping, &status), expected);
+    }
+}
@@ -1,10 +1,22 @@
 // Yeahbut May 2024
 
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use std::error::Error;
+use std::io;
+use std::net::SocketAddr;
 use rsa::RsaPrivateKey;
 
+use crate::packet_ids;
 use crate::whitelist::Whitelist;
+use crate::wake::WakeConfig;
+use crate::commands::CommandConfig;
+use crate::versions::VersionMessages;
+use crate::session_cookie::CookieConfig;
+use crate::proxy_protocol::{
+    self,
+    InboundProxyProtocol,
+    ProxyProtocolVersion,
+};
 
 #[derive(Copy, Clone)]
 pub enum OnlineStatus {
@@ -18,16 +30,118 @@ pub enum AuthenticationMethod {
     None,
 }
 
+// The client protocol versions a backend accepts, e.g. a range when it runs
+// ViaVersion
+#[derive(Clone)]
+pub enum ProtocolVersions {
+    Set(Vec<i32>),
+    // Inclusive
+    Range(i32, i32),
+}
+
+impl ProtocolVersions {
+    pub fn accepts(&self, protocol: i32) -> bool {
+        match self {
+            ProtocolVersions::Set(protocols) => protocols.contains(&protocol),
+            ProtocolVersions::Range(oldest, newest) =>
+                *oldest <= protocol && protocol <= *newest,
+        }
+    }
+
+    pub fn oldest(&self) -> i32 {
+        match self {
+            ProtocolVersions::Set(protocols) =>
+                protocols.iter().copied().min().unwrap_or_default(),
+            ProtocolVersions::Range(oldest, _) => *oldest,
+        }
+    }
+
+    pub fn newest(&self) -> i32 {
+        match self {
+            ProtocolVersions::Set(protocols) =>
+                protocols.iter().copied().max().unwrap_or_default(),
+            ProtocolVersions::Range(_, newest) => *newest,
+        }
+    }
+
+    // The accepted versions the proxy itself can log players in with
+    pub fn supported(&self) -> ProtocolVersions {
+        ProtocolVersions::Set(packet_ids::KNOWN_PROTOCOLS
+            .filter(|protocol| self.accepts(*protocol) &&
+                packet_ids::is_supported(*protocol))
+            .collect())
+    }
+}
+
+#[derive(Clone)]
+pub struct Backend {
+    pub name: String,
+    pub addr: String,
+    pub port: u16,
+    pub protocols: ProtocolVersions,
+    // Sends a PROXY header with the client's address, for backends that
+    // expect one
+    pub proxy_protocol: Option<ProxyProtocolVersion>,
+}
+
+impl Backend {
+    pub fn formatted_address(&self) -> String {
+        format!("{}:{}", self.addr, self.port)
+    }
+
+    // Refuses to start with a backend no client could join
+    pub fn check_protocols(&self) -> Result<(), String> {
+        match self.protocols.supported() {
+            ProtocolVersions::Set(protocols) if protocols.is_empty() =>
+                Err(format!("Backend {} accepts no protocol version the \
+proxy supports", self.name)),
+            _ => Ok(()),
+        }
+    }
+
+    // Connections the proxy makes for itself, like status checks, have no
+    // client address
+    pub async fn connect(
+        &self,
+        client_addr: Option<SocketAddr>,
+    ) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect(self.formatted_address()).await?;
+        if let Some(version) = self.proxy_protocol {
+            proxy_protocol::send_header(version, &mut stream, client_addr)
+                .await?;
+        }
+        Ok(stream)
+    }
+}
+
 #[derive(Clone)]
 pub struct ProxyInfo {
     pub proxy_addr: String,
     pub proxy_port: u16,
-    pub backend_addr: String,
-    pub backend_port: u16,
+    // The backend the connection is currently routed to
+    pub backend: Backend,
+    // Every backend players may switch between, including the default
+    pub backends: Vec<Backend>,
     pub private_key: RsaPrivateKey,
     pub online_status: OnlineStatus,
     pub authentication_method: AuthenticationMethod,
     pub whitelist: Whitelist,
+    pub wake: Option<WakeConfig>,
+    pub commands: CommandConfig,
+    // UDP port for the GameSpy4 query protocol, if enabled
+    pub query_port: Option<u16>,
+    pub version_messages: VersionMessages,
+    // Packets at least this large are compressed between the proxy and the
+    // client, None to disable compression
+    pub compression_threshold: Option<i32>,
+    // Lets players transferred from a proxy sharing the secret skip Mojang
+    // authentication
+    pub session_cookies: Option<CookieConfig>,
+    // Whether players sent here by a Transfer packet may log in, like the
+    // vanilla server's accepts-transfers
+    pub accepts_transfers: bool,
+    // Reads the real client address from a load balancer's PROXY header
+    pub proxy_protocol: Option<InboundProxyProtocol>,
 }
 
 impl ProxyInfo {
@@ -36,7 +150,22 @@ impl ProxyInfo {
     }
 
     pub fn formatted_backend_address(&self) -> String {
-        format!("{}:{}", self.backend_addr, self.backend_port)
+        self.backend.formatted_address()
+    }
+
+    // The backends whose players are counted in this listener's status
+    pub fn status_backends(&self) -> Vec<Backend> {
+        if self.backends.is_empty() {
+            vec![self.backend.clone()]
+        } else {
+            self.backends.clone()
+        }
+    }
+
+    pub fn find_backend(&self, name: &str) -> Option<Backend> {
+        self.backends.iter()
+            .find(|backend| backend.name.eq_ignore_ascii_case(name))
+            .cloned()
     }
 }
 
@@ -1,8 +1,11 @@
 // Yeahbut December 2023
 
+use std::io;
+use std::net::SocketAddr;
+use serde_json::json;
 
 use purple_cello_mc_protocol::{
-    mc_types::{self, Result, Packet, ProtocolConnection},
+    mc_types::{Result, Packet, ProtocolConnection, ProtocolRead, ProtocolWrite},
     handshake,
     login,
 };
@@ -10,11 +13,67 @@ use purple_cello_mc_protocol::{
 use purple_cello_mojang_api::multiplayer_auth;
 
 use crate::listener;
+use crate::chat;
+use crate::clock::{self, Clock};
+use crate::compression;
+use crate::info_messages;
+use crate::forge;
+use crate::login_plugins;
+use crate::packet_ids;
+use crate::raw_packet;
+use crate::session_cookie;
+use crate::play_handle::BackendLink;
 use crate::whitelist::{Player, PlayerAllowed};
 
+// Asks a transferred client for the session cookie a proxy stored on it
+// before the transfer
+async fn trusted_cookie(
+    proxy_info: &listener::ProxyInfo,
+    player: &Player,
+    client_addr: SocketAddr,
+    client_conn: &mut ProtocolConnection<'_>,
+) -> Result<bool> {
+    let config = match &proxy_info.session_cookies {
+        Some(config) => config,
+        None => return Ok(false),
+    };
+
+    let mut request = raw_packet::new_packet(
+        packet_ids::login::clientbound::COOKIE_REQUEST);
+    raw_packet::write_string(&mut request, session_cookie::COOKIE_KEY);
+    client_conn.write_data(&mut request).await?;
+
+    let response = client_conn.read_data().await?;
+    let mut index = 0;
+    if raw_packet::read_var_int(&response, &mut index) !=
+        Some(packet_ids::login::serverbound::COOKIE_RESPONSE) ||
+        raw_packet::read_string(&response, &mut index).as_deref() !=
+            Some(session_cookie::COOKIE_KEY) ||
+        response.get(index) != Some(&1) {
+            return Ok(false);
+    }
+    index += 1;
+    let length = raw_packet::read_var_int(&response, &mut index)
+        .unwrap_or_default().max(0) as usize;
+    let cookie = match response.get(index..index + length) {
+        Some(cookie) => cookie,
+        None => return Ok(false),
+    };
+
+    Ok(session_cookie::verify(
+        config,
+        cookie,
+        player,
+        client_addr.ip(),
+        clock::SystemClock.unix_seconds(),
+    ))
+}
+
 async fn check_player(
     proxy_info: &mut listener::ProxyInfo,
     player: Player,
+    client_addr: SocketAddr,
+    transferred: bool,
     client_conn: &mut ProtocolConnection<'_>,
 ) -> Result<PlayerAllowed> {
     match proxy_info.online_status {
@@ -29,6 +88,14 @@ async fn check_player(
             let server_id = client_conn.server_id_hash().await?;
             match proxy_info.authentication_method {
                 listener::AuthenticationMethod::Mojang => {
+                    if transferred && trusted_cookie(
+                        proxy_info, &player, client_addr, client_conn,
+                    ).await? {
+                        println!("Trusting session cookie from {}",
+                            player.name);
+                        return Ok(proxy_info.whitelist
+                            .check_player_whitelist(player));
+                    }
                     match multiplayer_auth::joined(
                         &player.name, &server_id, None).await {
                             Ok(_) => Ok(proxy_info.whitelist
@@ -49,33 +116,44 @@ async fn check_player(
 
 pub async fn respond_login(
     proxy_info: &mut listener::ProxyInfo,
+    protocol: i32,
+    client_addr: SocketAddr,
+    transferred: bool,
+    forge_marker: Option<&str>,
     client_conn: &mut ProtocolConnection<'_>,
-    server_conn: &mut ProtocolConnection<'_>,
-) -> Result<bool> {
-    let proxy_login = login_to_proxy(proxy_info, client_conn).await?;
+    backend: &mut BackendLink,
+) -> Result<Option<Player>> {
+    let proxy_login = login_to_proxy(
+        proxy_info, client_addr, transferred, client_conn).await?;
     match proxy_login {
         PlayerAllowed::True(player) => {
             println!("Player allowed");
-            login_to_backend(
+            if !login_to_backend(
                 proxy_info,
-                player,
+                protocol,
+                &player,
+                forge_marker,
                 client_conn,
-                server_conn,
-            ).await?;
-            return Ok(true)
+                backend,
+            ).await? {
+                return Ok(None)
+            }
+            return Ok(Some(player))
         },
         PlayerAllowed::False(msg) => {
             println!("Player blocked: {}", msg);
             login::clientbound::Disconnect {
                 reason: format!("{{\"text\":\"{}\"}}", msg.to_string())
             }.write(client_conn).await?;
-            return Ok(false)
+            return Ok(None)
         }
     }
 }
 
 async fn login_to_proxy(
     proxy_info: &mut listener::ProxyInfo,
+    client_addr: SocketAddr,
+    transferred: bool,
     client_conn: &mut ProtocolConnection<'_>,
 ) -> Result<PlayerAllowed> {
     println!("Logging into proxy");
@@ -87,42 +165,389 @@ async fn login_to_proxy(
         name: start_packet.name,
         player_uuid: start_packet.player_uuid,
         active: true,
+        group: None,
     };
 
-    check_player(proxy_info, player, client_conn).await
+    check_player(proxy_info, player, client_addr, transferred, client_conn)
+        .await
 }
 
-async fn login_to_backend(
-    proxy_info: &listener::ProxyInfo,
-    player: Player,
+// How a backend answered a login
+pub enum BackendLogin {
+    // Its Login Success packet, to be passed on to the client unchanged
+    Success(Vec<u8>),
+    // Its own Disconnect packet, e.g. if the player is banned there
+    Disconnected(Vec<u8>),
+    // It can't be used behind the proxy; the reason is for the player
+    Refused(String),
+}
+
+// The reason in a login Disconnect packet, as plain text
+pub fn disconnect_reason(packet: &[u8]) -> String {
+    let mut index = 0;
+    raw_packet::read_var_int(packet, &mut index);
+    raw_packet::read_string(packet, &mut index)
+        .map(|reason| match serde_json::from_str(&reason) {
+            Ok(component) => chat::legacy_text(&component),
+            Err(_) => reason,
+        })
+        .unwrap_or_default()
+}
+
+// Passes a Forge login plugin request to a client that is still logging in
+// and returns its answer
+async fn relay_plugin_request(
     client_conn: &mut ProtocolConnection<'_>,
-    server_conn: &mut ProtocolConnection<'_>,
-) -> Result<()> {
+    request: &[u8],
+    message_id: i32,
+) -> Result<Option<Vec<u8>>> {
+    client_conn.write_data(&mut request.to_vec()).await?;
+
+    let response = client_conn.read_data().await?;
+    let mut index = 0;
+    if raw_packet::read_var_int(&response, &mut index) !=
+        Some(packet_ids::login::serverbound::LOGIN_PLUGIN_RESPONSE) ||
+        raw_packet::read_var_int(&response, &mut index) != Some(message_id) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unexpected login plugin response from client",
+            ).into());
+    }
+    match response.get(index) {
+        Some(1) => Ok(Some(response[index + 1..].to_vec())),
+        _ => Ok(None),
+    }
+}
+
+// The client's own protocol version is forwarded so backends running
+// ViaVersion can translate for it, along with any Forge handshake marker.
+// While the client is still logging in it is passed to relay Forge login
+// plugin requests.
+pub async fn backend_login(
+    backend: &listener::Backend,
+    protocol: i32,
+    player: &Player,
+    forge_marker: Option<&str>,
+    mut client_conn: Option<&mut ProtocolConnection<'_>>,
+    link: &mut BackendLink,
+) -> Result<BackendLogin> {
     println!("Logging into backend");
     handshake::serverbound::Handshake {
-        protocol_version: mc_types::VERSION_PROTOCOL,
-        server_address: proxy_info.backend_addr.clone(),
-        server_port: proxy_info.backend_port,
+        protocol_version: protocol,
+        server_address: format!(
+            "{}{}", backend.addr, forge_marker.unwrap_or_default()),
+        server_port: backend.port,
         next_state: 2,
-    }.write(server_conn).await?;
+    }.write(&mut link.conn).await?;
 
     println!("Login start");
     login::serverbound::LoginStart {
-        name: player.name,
+        name: player.name.clone(),
         player_uuid: player.player_uuid,
-    }.write(server_conn).await?;
+    }.write(&mut link.conn).await?;
 
     println!("Finishing backend login");
-    let packet = login::clientbound::LoginSuccess::read(server_conn).await?;
+    loop {
+        let data = link.conn.read_data().await?;
+        let packet = compression::decode(link.compression, &data)?;
+        let mut index = 0;
+        match raw_packet::read_var_int(&packet, &mut index) {
+            Some(packet_ids::login::clientbound::SET_COMPRESSION) => {
+                let threshold = raw_packet::read_var_int(&packet, &mut index)
+                    .unwrap_or(-1);
+                println!("Backend compression threshold {}", threshold);
+                link.compression = if threshold >= 0 {
+                    Some(threshold)
+                } else {
+                    None
+                };
+            },
+            Some(packet_ids::login::clientbound::LOGIN_SUCCESS) =>
+                return Ok(BackendLogin::Success(packet)),
+            Some(packet_ids::login::clientbound::DISCONNECT) => {
+                println!("Backend {} disconnected {} during login: {}",
+                    backend.name, player.name, disconnect_reason(&packet));
+                return Ok(BackendLogin::Disconnected(packet));
+            },
+            Some(packet_ids::login::clientbound::ENCRYPTION_REQUEST) => {
+                println!("Backend {} asked {} for encryption. It must run \
+with online-mode=false behind the proxy.", backend.name, player.name);
+                return Ok(BackendLogin::Refused(
+                    info_messages::BACKEND_MISCONFIGURED_DISCONNECT
+                        .to_string()));
+            },
+            Some(packet_ids::login::clientbound::LOGIN_PLUGIN_REQUEST) => {
+                let message_id = raw_packet::read_var_int(&packet, &mut index)
+                    .unwrap_or_default();
+                let channel = raw_packet::read_string(&packet, &mut index)
+                    .unwrap_or_default();
+                let data = packet.get(index..).unwrap_or_default();
+
+                let mut answer = login_plugins::handle(&channel, player, data);
+                if answer.is_none() && forge::is_login_channel(&channel) {
+                    if let Some(client_conn) = client_conn.as_mut() {
+                        answer = relay_plugin_request(
+                            client_conn, &packet, message_id).await?;
+                    }
+                }
+
+                let mut response = raw_packet::new_packet(
+                    packet_ids::login::serverbound::LOGIN_PLUGIN_RESPONSE);
+                raw_packet::write_var_int(&mut response, message_id);
+                match answer {
+                    Some(data) => {
+                        response.push(1);
+                        response.extend(data);
+                    },
+                    None => {
+                        println!("Backend {} login plugin channel {} not \
+understood", backend.name, channel);
+                        response.push(0);
+                    },
+                }
+                let mut frame =
+                    compression::encode(link.compression, &response);
+                link.conn.write_data(&mut frame).await?;
+            },
+            packet_id => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected login packet {:?} from backend", packet_id),
+            ).into()),
+        }
+    }
+}
+
+async fn login_to_backend(
+    proxy_info: &listener::ProxyInfo,
+    protocol: i32,
+    player: &Player,
+    forge_marker: Option<&str>,
+    client_conn: &mut ProtocolConnection<'_>,
+    backend: &mut BackendLink,
+) -> Result<bool> {
+    let login = backend_login(
+        &proxy_info.backend,
+        protocol,
+        player,
+        forge_marker,
+        Some(&mut *client_conn),
+        backend,
+    ).await?;
+    let packet = match login {
+            BackendLogin::Success(packet) => packet,
+            BackendLogin::Disconnected(mut packet) => {
+                client_conn.write_data(&mut packet).await?;
+                return Ok(false);
+            },
+            BackendLogin::Refused(reason) => {
+                login::clientbound::Disconnect {
+                    reason: json!({"text": reason}).to_string(),
+                }.write(client_conn).await?;
+                return Ok(false);
+            },
+    };
+
+    // The client's threshold is the listener's own, whatever the backend uses
+    if let Some(threshold) = proxy_info.compression_threshold {
+        let mut set_compression = raw_packet::new_packet(
+            packet_ids::login::clientbound::SET_COMPRESSION);
+        raw_packet::write_var_int(&mut set_compression, threshold);
+        client_conn.write_data(&mut set_compression).await?;
+    }
 
     println!("Finishing proxy login");
-    login::clientbound::LoginSuccess {
-        uuid: packet.uuid.clone(),
-        username: packet.username.clone(),
-        properties: packet.properties.clone(),
-    }.write(client_conn).await?;
+    let mut login_success =
+        compression::encode(proxy_info.compression_threshold, &packet);
+    client_conn.write_data(&mut login_success).await?;
 
     println!("Client logged in");
 
-    Ok(())
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const PROTOCOL: i32 = 767;
+    const THRESHOLD: i32 = 256;
+
+    async fn read_frame(stream: &mut TcpStream) -> Vec<u8> {
+        let mut length = 0;
+        for position in 0..5 {
+            let byte = stream.read_u8().await.unwrap();
+            length |= ((byte & 0x7F) as usize) << (7 * position);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut frame = vec![0; length];
+        stream.read_exact(&mut frame).await.unwrap();
+        frame
+    }
+
+    async fn write_frame(stream: &mut TcpStream, frame: &[u8]) {
+        let mut data = Vec::new();
+        raw_packet::write_var_int(&mut data, frame.len() as i32);
+        data.extend_from_slice(frame);
+        stream.write_all(&data).await.unwrap();
+    }
+
+    // Large enough to be compressed
+    fn login_success() -> Vec<u8> {
+        let mut packet = raw_packet::new_packet(
+            packet_ids::login::clientbound::LOGIN_SUCCESS);
+        packet.extend_from_slice(&1u128.to_be_bytes());
+        raw_packet::write_string(&mut packet, "Purple");
+        raw_packet::write_var_int(&mut packet, 1);
+        raw_packet::write_string(&mut packet, "textures");
+        raw_packet::write_string(&mut packet, &"a".repeat(600));
+        packet.push(0);
+        packet
+    }
+
+    #[tokio::test]
+    async fn backend_enables_compression() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let fake_backend = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let handshake = read_frame(&mut stream).await;
+            let mut index = 0;
+            assert_eq!(raw_packet::read_var_int(&handshake, &mut index),
+                Some(0x00));
+            // The client's version is forwarded
+            assert_eq!(raw_packet::read_var_int(&handshake, &mut index),
+                Some(PROTOCOL));
+            let _login_start = read_frame(&mut stream).await;
+
+            let mut set_compression = raw_packet::new_packet(
+                packet_ids::login::clientbound::SET_COMPRESSION);
+            raw_packet::write_var_int(&mut set_compression, THRESHOLD);
+            write_frame(&mut stream, &set_compression).await;
+
+            let frame = compression::encode(Some(THRESHOLD), &login_success());
+            assert!(frame.len() < login_success().len());
+            write_frame(&mut stream, &frame).await;
+        });
+
+        let backend = listener::Backend {
+            name: "test".to_string(),
+            addr: "127.0.0.1".to_string(),
+            port,
+            protocols: listener::ProtocolVersions::Set(vec![PROTOCOL]),
+            proxy_protocol: None,
+        };
+        let mut link = BackendLink::new(
+            TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+
+        let login = backend_login(
+            &backend, PROTOCOL, &player(), None, None, &mut link)
+            .await.unwrap();
+        assert_eq!(link.compression, Some(THRESHOLD));
+        match login {
+            BackendLogin::Success(packet) =>
+                assert_eq!(packet, login_success()),
+            _ => panic!("backend login did not succeed"),
+        }
+
+        fake_backend.await.unwrap();
+    }
+
+    fn player() -> Player {
+        Player {
+            name: "Purple".to_string(),
+            player_uuid: Some(1),
+            active: true,
+            group: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn forge_login_is_relayed() {
+        const MARKER: &str = "\0FML3\0";
+        const MESSAGE_ID: i32 = 7;
+
+        let mut request = raw_packet::new_packet(
+            packet_ids::login::clientbound::LOGIN_PLUGIN_REQUEST);
+        raw_packet::write_var_int(&mut request, MESSAGE_ID);
+        raw_packet::write_string(&mut request, "fml:loginwrapper");
+        request.extend_from_slice(&[1, 2, 3]);
+
+        let mut response = raw_packet::new_packet(
+            packet_ids::login::serverbound::LOGIN_PLUGIN_RESPONSE);
+        raw_packet::write_var_int(&mut response, MESSAGE_ID);
+        response.push(1);
+        response.extend_from_slice(&[4, 5, 6]);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let backend_request = request.clone();
+        let backend_response = response.clone();
+        let fake_backend = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let handshake = read_frame(&mut stream).await;
+            let mut index = 0;
+            raw_packet::read_var_int(&handshake, &mut index);
+            raw_packet::read_var_int(&handshake, &mut index);
+            assert_eq!(raw_packet::read_string(&handshake, &mut index),
+                Some(format!("127.0.0.1{}", MARKER)));
+            let _login_start = read_frame(&mut stream).await;
+
+            write_frame(&mut stream, &backend_request).await;
+            // The modded client's answer, not "not understood"
+            assert_eq!(read_frame(&mut stream).await, backend_response);
+
+            let mut login_success = raw_packet::new_packet(
+                packet_ids::login::clientbound::LOGIN_SUCCESS);
+            login_success.extend_from_slice(&1u128.to_be_bytes());
+            raw_packet::write_string(&mut login_success, "Purple");
+            write_frame(&mut stream, &login_success).await;
+        });
+
+        let client_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut fake_client = TcpStream::connect(
+            client_listener.local_addr().unwrap()).await.unwrap();
+        let (proxy_side, _) = client_listener.accept().await.unwrap();
+        let client_request = request.clone();
+        let client_response = response.clone();
+        let fake_client = tokio::spawn(async move {
+            assert_eq!(read_frame(&mut fake_client).await, client_request);
+            write_frame(&mut fake_client, &client_response).await;
+        });
+
+        let backend = listener::Backend {
+            name: "forge".to_string(),
+            addr: "127.0.0.1".to_string(),
+            port,
+            protocols: listener::ProtocolVersions::Set(vec![PROTOCOL]),
+            proxy_protocol: None,
+        };
+        let (mut client_reader, mut client_writer) = proxy_side.into_split();
+        let mut client_conn = ProtocolConnection::new(
+            &mut client_reader,
+            &mut client_writer,
+        );
+        let mut link = BackendLink::new(
+            TcpStream::connect(("127.0.0.1", port)).await.unwrap());
+
+        let login = backend_login(
+            &backend,
+            PROTOCOL,
+            &player(),
+            Some(MARKER),
+            Some(&mut client_conn),
+            &mut link,
+        ).await.unwrap();
+        assert!(matches!(login, BackendLogin::Success(_)));
+
+        fake_client.await.unwrap();
+        fake_backend.await.unwrap();
+    }
 }
@@ -0,0 +1,19 @@
+// Yeahbut October 2026
+
+// Answers to the Login Plugin Requests a backend may send before Login
+// Success, by channel. Forge's channels are relayed to the client, and
+// requests on any other channel are answered "not understood", as the vanilla
+// client does.
+
+use crate::whitelist::Player;
+
+// Returns the response data, or None if the request was not understood
+pub type Handler = fn(player: &Player, data: &[u8]) -> Option<Vec<u8>>;
+
+const HANDLERS: &[(&str, Handler)] = &[];
+
+pub fn handle(channel: &str, player: &Player, data: &[u8]) -> Option<Vec<u8>> {
+    HANDLERS.iter()
+        .find(|(registered, _)| *registered == channel)
+        .and_then(|(_, handler)| handler(player, data))
+}
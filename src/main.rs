@@ -1,6 +1,7 @@
 // Yeahbut December 2023
 
 use std::error::Error;
+use std::collections::HashMap;
 
 use purple_cello_mc_protocol::encrypt;
 
@@ -11,33 +12,108 @@ mod listener;
 mod whitelist;
 mod info_messages;
 mod motd;
+mod wake;
+mod play_handle;
+mod packet_ids;
+mod raw_packet;
+mod chat;
+mod commands;
+mod sessions;
+mod admin;
+mod status_cache;
+mod minimessage;
+mod clock;
+mod favicon;
+mod legacy_ping;
+mod query;
+mod versions;
+mod compression;
+mod connection_state;
+mod login_plugins;
+mod session_cookie;
+mod proxy_protocol;
+mod forge;
+mod config;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let proxy_config = config::load();
     let private_key = encrypt::generate_rsa_keys()?;
+    let backends = proxy_config.backends.clone();
+    let main_backend = backends[0].clone();
+    for backend in &backends {
+        backend.check_protocols()?;
+    }
+    let command_config = commands::CommandConfig {
+        enabled: vec![
+            "proxy".to_string(),
+            "server".to_string(),
+            "list".to_string(),
+            "glist".to_string(),
+            "transfer".to_string(),
+        ],
+        permissions: HashMap::from([
+            ("transfer".to_string(), vec!["admin".to_string()]),
+        ]),
+    };
+    let version_messages = versions::VersionMessages {
+        outdated: info_messages::OUTDATED_VERSION_DISCONNECT.to_string(),
+        future: info_messages::FUTURE_VERSION_DISCONNECT.to_string(),
+    };
     let offline_info = listener::ProxyInfo{
         proxy_addr: "127.0.0.1".to_string(),
         proxy_port: 25565,
-        backend_addr: "127.0.0.1".to_string(),
-        backend_port: 25564,
+        backend: main_backend.clone(),
+        backends: backends.clone(),
         private_key: private_key.clone(),
         online_status: listener::OnlineStatus::Offline,
         authentication_method: listener::AuthenticationMethod::None,
         whitelist: whitelist::Whitelist::WhitelistOpen(
             whitelist::WhitelistOpen{}),
+        wake: proxy_config.wake.clone(),
+        commands: command_config.clone(),
+        query_port: proxy_config.offline.query_port,
+        version_messages: version_messages.clone(),
+        compression_threshold: Some(256),
+        session_cookies: proxy_config.session_cookies.clone(),
+        accepts_transfers: proxy_config.accepts_transfers,
+        proxy_protocol: proxy_config.offline.proxy_protocol.clone(),
     };
     let online_info = listener::ProxyInfo{
         proxy_addr: "127.0.0.1".to_string(),
         proxy_port: 25566,
-        backend_addr: "127.0.0.1".to_string(),
-        backend_port: 25564,
+        backend: main_backend.clone(),
+        backends: backends.clone(),
         private_key: private_key.clone(),
         online_status: listener::OnlineStatus::Online,
         authentication_method: listener::AuthenticationMethod::Mojang,
         whitelist: whitelist::Whitelist::WhitelistFile(
             whitelist::WhitelistFile::new("./whitelist.json".to_string())),
+        wake: proxy_config.wake.clone(),
+        commands: command_config.clone(),
+        query_port: proxy_config.online.query_port,
+        version_messages: version_messages.clone(),
+        compression_threshold: Some(256),
+        session_cookies: proxy_config.session_cookies.clone(),
+        accepts_transfers: proxy_config.accepts_transfers,
+        proxy_protocol: proxy_config.online.proxy_protocol.clone(),
     };
 
+    for backend in &backends {
+        let mut info = offline_info.clone();
+        info.backend = backend.clone();
+        tokio::spawn(status_cache::refresher(info));
+    }
+
+    for info in [&offline_info, &online_info] {
+        if info.wake.is_some() {
+            tokio::spawn(wake::idle_monitor(info.clone()));
+        }
+        if let Some(port) = info.query_port {
+            tokio::spawn(query::query_listener(info.clone(), port));
+        }
+    }
+
     let listener_offline: listener::TcpListenerWrapper =
         listener::TcpListenerWrapper::bind(offline_info).await?;
     let listener_online: listener::TcpListenerWrapper =
@@ -45,18 +121,20 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     println!("Proxy listening on port 25565 and 25566...");
 
+    tokio::spawn(admin::console());
+
     let handle_offline = tokio::spawn(async move{
-        while let Ok((client_socket, _)) = listener_offline
+        while let Ok((client_socket, client_addr)) = listener_offline
             .listener.accept().await {
                 tokio::spawn(client::handle_client(
-                    client_socket, listener_offline.info.clone()));
+                    client_socket, client_addr, listener_offline.info.clone()));
         }
     });
     let handle_online = tokio::spawn(async move{
-        while let Ok((client_socket, _)) = listener_online
+        while let Ok((client_socket, client_addr)) = listener_online
             .listener.accept().await {
                 tokio::spawn(client::handle_client(
-                    client_socket, listener_online.info.clone()));
+                    client_socket, client_addr, listener_online.info.clone()));
         }
     });
 
@@ -0,0 +1,278 @@
+// Yeahbut October 2026
+
+// A small MiniMessage-style markup parser, e.g. "<gold>Purple <b>Cello", that
+// produces chat components. Supported tags are the named colors, hex colors
+// (<#RRGGBB> or <color:...>), decorations, <reset> and
+// <gradient:#RRGGBB:#RRGGBB...>.
+
+use serde_json::{json, Map, Value};
+
+pub const NAMED_COLORS: [&str; 16] = [
+    "black", "dark_blue", "dark_green", "dark_aqua",
+    "dark_red", "dark_purple", "gold", "gray",
+    "dark_gray", "blue", "green", "aqua",
+    "red", "light_purple", "yellow", "white",
+];
+
+const DECORATIONS: [(&str, &str); 10] = [
+    ("b", "bold"), ("bold", "bold"),
+    ("i", "italic"), ("italic", "italic"),
+    ("u", "underlined"), ("underlined", "underlined"),
+    ("st", "strikethrough"), ("strikethrough", "strikethrough"),
+    ("obf", "obfuscated"), ("obfuscated", "obfuscated"),
+];
+
+pub fn is_valid_color(color: &str) -> bool {
+    NAMED_COLORS.contains(&color) || parse_hex(color).is_some()
+}
+
+fn parse_hex(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+fn normalize_color(color: &str) -> Option<String> {
+    let color = color.to_lowercase();
+    let color = match color.as_str() {
+        "grey" => "gray".to_string(),
+        "dark_grey" => "dark_gray".to_string(),
+        _ => color,
+    };
+    if is_valid_color(&color) {
+        Some(color)
+    } else {
+        None
+    }
+}
+
+enum Tag {
+    Color(String),
+    Decoration(&'static str),
+    Gradient(Vec<(u8, u8, u8)>),
+}
+
+fn parse_tag(tag: &str) -> Option<(String, Tag)> {
+    let (name, args) = match tag.split_once(':') {
+        Some((name, args)) => (name.to_lowercase(), Some(args)),
+        None => (tag.to_lowercase(), None),
+    };
+
+    if let Some((_, decoration)) = DECORATIONS.iter()
+        .find(|(short, _)| *short == name) {
+            return Some((name, Tag::Decoration(*decoration)));
+    }
+
+    match (name.as_str(), args) {
+        ("color" | "c", Some(color)) =>
+            Some((name.clone(), Tag::Color(normalize_color(color)?))),
+        ("gradient", Some(colors)) => {
+            let colors: Option<Vec<(u8, u8, u8)>> = colors.split(':')
+                .map(|color| normalize_color(color)
+                    .and_then(|color| parse_hex(&color)))
+                .collect();
+            let colors = colors?;
+            if colors.len() < 2 {
+                return None;
+            }
+            Some((name, Tag::Gradient(colors)))
+        },
+        (_, None) => Some((name.clone(), Tag::Color(normalize_color(&name)?))),
+        _ => None,
+    }
+}
+
+fn interpolate(colors: &[(u8, u8, u8)], position: f64) -> String {
+    let scaled = position * (colors.len() - 1) as f64;
+    let index = (scaled.floor() as usize).min(colors.len() - 2);
+    let local = scaled - index as f64;
+    let (a, b) = (colors[index], colors[index + 1]);
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * local)
+        .round() as u8;
+    format!("#{:02X}{:02X}{:02X}", mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+}
+
+struct Parser {
+    stack: Vec<(String, Tag)>,
+    components: Vec<Value>,
+    text: String,
+    // Characters waiting for the innermost gradient to close
+    gradient_text: Vec<(char, Map<String, Value>)>,
+}
+
+impl Parser {
+    fn style(&self) -> Map<String, Value> {
+        let mut style = Map::new();
+        for (_, tag) in &self.stack {
+            match tag {
+                Tag::Color(color) => {
+                    style.insert("color".to_string(), json!(color));
+                },
+                Tag::Decoration(decoration) => {
+                    style.insert(decoration.to_string(), json!(true));
+                },
+                Tag::Gradient(_) => {},
+            }
+        }
+        style
+    }
+
+    fn gradient(&self) -> Option<&Vec<(u8, u8, u8)>> {
+        self.stack.iter().rev().find_map(|(_, tag)| match tag {
+            Tag::Gradient(colors) => Some(colors),
+            _ => None,
+        })
+    }
+
+    fn flush_text(&mut self) {
+        if self.text.is_empty() {
+            return;
+        }
+        let text = std::mem::take(&mut self.text);
+        let style = self.style();
+        if self.gradient().is_some() {
+            for character in text.chars() {
+                self.gradient_text.push((character, style.clone()));
+            }
+        } else {
+            let mut component = style;
+            component.insert("text".to_string(), json!(text));
+            self.components.push(Value::Object(component));
+        }
+    }
+
+    fn flush_gradient(&mut self, colors: &[(u8, u8, u8)]) {
+        let characters = std::mem::take(&mut self.gradient_text);
+        let steps = characters.len().saturating_sub(1).max(1) as f64;
+        for (index, (character, style)) in characters.into_iter().enumerate() {
+            let mut component = style;
+            component.insert("text".to_string(), json!(character.to_string()));
+            component.insert("color".to_string(),
+                json!(interpolate(colors, index as f64 / steps)));
+            self.components.push(Value::Object(component));
+        }
+    }
+
+    fn close(&mut self, name: &str) -> bool {
+        match self.stack.iter().rposition(|(open, _)| open == name) {
+            Some(position) => {
+                self.close_from(position);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Closes the tag at position and everything opened after it
+    fn close_from(&mut self, position: usize) {
+        self.flush_text();
+        while self.stack.len() > position {
+            if let Some((_, Tag::Gradient(colors))) = self.stack.pop() {
+                if self.gradient().is_none() {
+                    self.flush_gradient(&colors);
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.close_from(0);
+    }
+}
+
+// Whether the text uses any markup this parser understands
+pub fn has_markup(text: &str) -> bool {
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => return false,
+        };
+        let tag = rest[..end].trim_start_matches('/');
+        if tag.eq_ignore_ascii_case("reset") || parse_tag(tag).is_some() {
+            return true;
+        }
+    }
+    false
+}
+
+pub fn parse(markup: &str) -> Value {
+    let mut parser = Parser {
+        stack: Vec::new(),
+        components: Vec::new(),
+        text: String::new(),
+        gradient_text: Vec::new(),
+    };
+
+    let mut rest = markup;
+    while !rest.is_empty() {
+        if let Some(escaped) = rest.strip_prefix("\\<") {
+            parser.text.push('<');
+            rest = escaped;
+            continue;
+        }
+
+        if rest.starts_with('<') {
+            if let Some(end) = rest.find('>') {
+                let tag = &rest[1..end];
+                let handled = if let Some(name) = tag.strip_prefix('/') {
+                    parser.close(&name.to_lowercase())
+                } else if tag.eq_ignore_ascii_case("reset") {
+                    parser.reset();
+                    true
+                } else if let Some(parsed) = parse_tag(tag) {
+                    parser.flush_text();
+                    parser.stack.push(parsed);
+                    true
+                } else {
+                    false
+                };
+
+                if handled {
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+
+        let character = rest.chars().next().unwrap_or_default();
+        parser.text.push(character);
+        rest = &rest[character.len_utf8()..];
+    }
+
+    parser.reset();
+    parser.flush_text();
+
+    // The client rejects an empty extra list
+    if parser.components.is_empty() {
+        return json!({"text": ""});
+    }
+    json!({
+        "text": "",
+        "extra": parser.components,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_closes_everything() {
+        let parsed = parse("<gold>a<b>b<gold>c<reset>d");
+        let last = parsed["extra"].as_array().unwrap().last().unwrap().clone();
+        assert_eq!(last, json!({"text": "d"}));
+
+        let parsed = parse(
+            "<gradient:#FF0000:#0000FF>Hi <gradient:#00FF00:#0000FF>there");
+        let text: String = parsed["extra"].as_array().unwrap().iter()
+            .map(|component| component["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(text, "Hi there");
+        assert_eq!(parsed["extra"][0]["color"], "#FF0000");
+    }
+}
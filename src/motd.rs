@@ -1,15 +1,201 @@
 // Yeahbut June 2024
 
-use std::fs::{self, File};
-use std::io::Read;
+use std::fs;
 use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex};
 
-use serde_json::Value;
-use base64::{Engine as _, engine::general_purpose};
+use serde_json::{json, Value};
 use rand::Rng;
 use lazy_static::lazy_static;
 
+use crate::chat;
+use crate::clock;
+use crate::favicon;
+use crate::minimessage;
+
+// Values for the placeholders MOTD lines may use
+pub struct MotdContext {
+    pub online: i32,
+    pub max: i32,
+    pub version: String,
+    pub backend_status: String,
+    pub player_name: Option<String>,
+}
+
+impl MotdContext {
+    fn fill(&self, text: &str, date: clock::Date) -> String {
+        text.replace("{online}", &self.online.to_string())
+            .replace("{max}", &self.max.to_string())
+            .replace("{version}", &self.version)
+            .replace("{backend_status}", &self.backend_status)
+            .replace("{player_name}",
+                self.player_name.as_deref().unwrap_or_default())
+            .replace("{date}", &date.to_string())
+    }
+
+    fn fill_component(&self, component: &mut Value, date: clock::Date) {
+        match component {
+            Value::String(text) => *text = self.fill(text, date),
+            Value::Array(components) => components.iter_mut()
+                .for_each(|component| self.fill_component(component, date)),
+            Value::Object(map) => {
+                if let Some(Value::String(text)) = map.get_mut("text") {
+                    *text = self.fill(text, date);
+                }
+                if let Some(extra) = map.get_mut("extra") {
+                    self.fill_component(extra, date);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+fn uses_player_name(component: &Value) -> bool {
+    component.to_string().contains("{player_name}")
+}
+
+// Picks a line at random by weight. Lines naming the player are only used
+// when the player is known, so None if every line needs a name.
+fn pick_line(
+    lines: Vec<(Value, u64)>,
+    context: &MotdContext,
+    date: clock::Date,
+) -> Option<Value> {
+    let usable: Vec<(Value, u64)> = lines.into_iter()
+        .filter(|(line, _)| context.player_name.is_some() ||
+            !uses_player_name(line))
+        .collect();
+
+    let total: u64 = usable.iter().map(|(_, weight)| weight).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut choice = rand::thread_rng().gen_range(0..total);
+    for (mut line, weight) in usable {
+        if choice < weight {
+            context.fill_component(&mut line, date);
+            return Some(line);
+        }
+        choice -= weight;
+    }
+    None
+}
+
+fn parse_date(date: &str) -> Option<clock::Date> {
+    let mut parts = date.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    Some(clock::Date { year, month, day })
+}
+
+fn parse_month_day(month_day: &str) -> Option<(u32, u32)> {
+    let (month, day) = month_day.split_once('-')?;
+    Some((month.parse().ok()?, day.parse().ok()?))
+}
+
+// Scheduled entries are active on a "date", on a "yearly" month and day, on
+// a player's "birthday", or between "from" and "to" (both full dates, or
+// both month and day, which may wrap around the new year)
+fn is_active(entry: &Value, date: clock::Date) -> bool {
+    let month_day = (date.month, date.day);
+
+    if let Some(fixed) = entry["date"].as_str() {
+        return parse_date(fixed) == Some(date);
+    }
+    if let Some(yearly) = entry["yearly"].as_str() {
+        return parse_month_day(yearly) == Some(month_day);
+    }
+    if let Some(birthday) = entry["birthday"]["date"].as_str() {
+        return parse_month_day(birthday) == Some(month_day);
+    }
+    if let (Some(from), Some(to)) = (entry["from"].as_str(), entry["to"].as_str()) {
+        if let (Some(from), Some(to)) = (parse_date(from), parse_date(to)) {
+            return from <= date && date <= to;
+        }
+        if let (Some(from), Some(to)) =
+            (parse_month_day(from), parse_month_day(to)) {
+                return if from <= to {
+                    from <= month_day && month_day <= to
+                } else {
+                    month_day >= from || month_day <= to
+                };
+        }
+    }
+    false
+}
+
+fn active_entries(motd_data: &Value, date: clock::Date) -> Vec<Value> {
+    motd_data["scheduled"].as_array()
+        .map(|entries| entries.iter()
+            .filter(|entry| is_active(entry, date))
+            .cloned()
+            .collect())
+        .unwrap_or_default()
+}
+
+// Lines for a player by name under "players", then lines for any known
+// visitor under "visitor", then the generic lines
+fn base_lines<'a>(
+    motd_data: &'a Value,
+    key: &str,
+    player_name: Option<&str>,
+) -> &'a [Value] {
+    let personalised = player_name.into_iter()
+        .flat_map(|name| [
+            &motd_data["players"][name],
+            &motd_data["visitor"],
+        ])
+        .filter_map(|lines| lines[key].as_array())
+        .find(|lines| !lines.is_empty());
+
+    match personalised.or(motd_data[key].as_array()) {
+        Some(lines) => lines.as_slice(),
+        None => &[],
+    }
+}
+
+// Scheduled lines without a weight replace the random lines; weighted ones
+// join them, each regular line counting as weight 1
+fn candidate_lines(
+    motd_data: &Value,
+    active: &[Value],
+    key: &str,
+    player_name: Option<&str>,
+) -> Vec<(Value, u64)> {
+    let mut overrides: Vec<(Value, u64)> = Vec::new();
+    let mut weighted: Vec<(Value, u64)> = Vec::new();
+
+    for entry in active {
+        let mut line = match entry.get(key) {
+            Some(line) => line.clone(),
+            None => continue,
+        };
+        // Player names need no JSON escaping
+        if let Some(name) = entry["birthday"]["name"].as_str() {
+            let filled = line.to_string().replace("{birthday_name}", name);
+            line = serde_json::from_str(&filled).unwrap_or(line);
+        }
+        match entry["weight"].as_u64() {
+            Some(weight) => weighted.push((line, weight)),
+            None => overrides.push((line, 1)),
+        }
+    }
+
+    if !overrides.is_empty() {
+        return overrides;
+    }
+
+    let mut lines: Vec<(Value, u64)> =
+        base_lines(motd_data, key, player_name).iter()
+            .map(|line| (line.clone(), 1))
+            .collect();
+    lines.extend(weighted);
+    lines
+}
+
 // Refresh every 60 minutes
 const EXPIRATION_DURATION: Duration = Duration::from_secs(3600);
 
@@ -26,14 +212,92 @@ fn load_motds() -> Value {
         Err(_) => return Value::Null,
     };
 
-    let motd_data: Value = match serde_json::from_str(&data) {
+    let mut motd_data: Value = match serde_json::from_str(&data) {
         Ok(value) => value,
         Err(_) => return Value::Null,
     };
+    // Indexing below inserts keys, which panics on anything but an object
+    if !motd_data.is_object() {
+        println!("Ignoring motd.json, its top level is not an object");
+        return Value::Null;
+    }
+
+    convert_lines(&mut motd_data);
+    convert_lines(&mut motd_data["visitor"]);
+    convert_lines(&mut motd_data["offline"]);
+    convert_lines(&mut motd_data["starting"]);
+    if let Some(players) = motd_data["players"].as_object_mut() {
+        for lines in players.values_mut() {
+            convert_lines(lines);
+        }
+    }
+
+    if let Some(entries) = motd_data["scheduled"].as_array_mut() {
+        for entry in entries.iter_mut() {
+            for key in ["line1", "line2"] {
+                let line = match entry.get(key) {
+                    Some(line) => line.clone(),
+                    None => continue,
+                };
+                match to_component(&line) {
+                    Ok(component) => entry[key] = component,
+                    Err(e) => {
+                        println!("Skipping invalid scheduled MOTD {}: {}",
+                            key, e);
+                        if let Some(entry) = entry.as_object_mut() {
+                            entry.remove(key);
+                        }
+                    },
+                }
+            }
+        }
+    }
+
+    if let Some(timezone) = motd_data["timezone"].as_str() {
+        if clock::parse_utc_offset(timezone).is_none() {
+            println!("Invalid MOTD timezone {}, using UTC", timezone);
+        }
+    }
 
     motd_data
 }
 
+fn convert_lines(motd_lines: &mut Value) {
+    for key in ["line1", "line2"] {
+        if let Some(lines) = motd_lines[key].as_array() {
+            let components: Vec<Value> = lines.iter()
+                .filter_map(|line| match to_component(line) {
+                    Ok(component) => Some(component),
+                    Err(e) => {
+                        println!("Skipping invalid MOTD {} entry: {}", key, e);
+                        None
+                    },
+                })
+                .collect();
+            motd_lines[key] = Value::Array(components);
+        }
+    }
+}
+
+// MOTD entries may be plain strings (legacy formatting codes still work),
+// MiniMessage-style markup, or chat components
+fn to_component(line: &Value) -> Result<Value, String> {
+    match line {
+        Value::String(text) if minimessage::has_markup(text) =>
+            Ok(minimessage::parse(text)),
+        Value::String(text) => Ok(json!({"text": text})),
+        Value::Array(_) => {
+            chat::validate_component(line)?;
+            Ok(json!({"text": "", "extra": line}))
+        },
+        Value::Object(_) => {
+            chat::validate_component(line)?;
+            Ok(line.clone())
+        },
+        _ => Err(format!("not a string or component: {}", line)),
+    }
+}
+
 fn get_motds() -> Value {
     lazy_static! {
         static ref MOTDS_CACHE: Arc<Mutex<Option<CachedMotds>>> =
@@ -64,8 +328,22 @@ fn get_motds() -> Value {
     motds
 }
 
-pub fn motd() -> String {
-    let default = "A Minecraft Server Proxy".to_string();
+pub fn motd(context: &MotdContext) -> Value {
+    motd_at(context, &clock::SystemClock)
+}
+
+fn utc_offset(motd_data: &Value) -> i64 {
+    motd_data["timezone"].as_str()
+        .and_then(clock::parse_utc_offset)
+        .unwrap_or(0)
+}
+
+fn motd_date(motd_data: &Value, clock: &dyn clock::Clock) -> clock::Date {
+    clock::date_at(clock, utc_offset(motd_data))
+}
+
+pub fn motd_at(context: &MotdContext, clock: &dyn clock::Clock) -> Value {
+    let default = json!({"text": "A Minecraft Server Proxy"});
 
     let motd_data = get_motds();
 
@@ -73,49 +351,194 @@ pub fn motd() -> String {
         return default;
     }
 
-    let length1 = motd_data["line1"].as_array().map_or(0, |v| v.len());
-    let length2 = motd_data["line2"].as_array().map_or(0, |v| v.len());
-
-    if length1 == 0 || length2 == 0 {
-        return default;
-    }
+    let date = motd_date(&motd_data, clock);
+    let active = active_entries(&motd_data, date);
 
-    let mut rng = rand::thread_rng();
-    let rand1 = rng.gen_range(0..length1) as usize;
-    let rand2 = rng.gen_range(0..length2) as usize;
+    let player_name = context.player_name.as_deref();
 
-    let line1: &str = match motd_data["line1"][rand1].as_str() {
-        Some(s) => s,
+    let line1 = match pick_line(
+        candidate_lines(&motd_data, &active, "line1", player_name),
+        context,
+        date,
+    ) {
+        Some(line) => line,
         None => return default,
     };
 
-    // TODO: Birthdays, Holidays, and Announcements
-
-    let line2: &str = match motd_data["line2"][rand2].as_str() {
-        Some(s) => s,
+    let line2 = match pick_line(
+        candidate_lines(&motd_data, &active, "line2", player_name),
+        context,
+        date,
+    ) {
+        Some(line) => line,
         None => return default,
     };
 
-    let line: String = format!("{}\n{}", line1, line2);
-    line
+    json!({
+        "text": "",
+        "extra": [line1, "\n", line2],
+    })
 }
 
-pub fn favicon() -> Option<String> {
-    let file_path = "./icon.png";
+// Lines under "offline", or "starting" while a start is in progress, shown
+// when every backend is down. None if they are not configured.
+pub fn offline_motd(context: &MotdContext, starting: bool) -> Option<Value> {
+    let motd_data = get_motds();
+    let date = motd_date(&motd_data, &clock::SystemClock);
 
-    let mut file = match File::open(file_path) {
-        Ok(file) => file,
-        Err(_) => return None,
+    let section = if starting && motd_data["starting"].is_object() {
+        "starting"
+    } else {
+        "offline"
     };
-
-    let mut buffer = Vec::new();
-    if let Err(_) = file.read_to_end(&mut buffer) {
-        return None
+    let lines = |key: &str| -> Vec<(Value, u64)> {
+        motd_data[section][key].as_array()
+            .map(|lines| lines.iter()
+                .map(|line| (line.clone(), 1))
+                .collect())
+            .unwrap_or_default()
     };
 
-    let base64_string = general_purpose::STANDARD_NO_PAD.encode(buffer);
-    let full_string: String =
-        format!("data:image/png;base64,{}", base64_string);
+    let line1 = pick_line(lines("line1"), context, date)?;
+    let line2 = pick_line(lines("line2"), context, date)?;
+
+    Some(json!({
+        "text": "",
+        "extra": [line1, "\n", line2],
+    }))
+}
+
+// Scheduled entries may name an icon from the icon directory; otherwise
+// "icon_rotation" picks one at random on every ping or one per day
+pub fn icon_selection() -> favicon::IconSelection {
+    icon_selection_at(&clock::SystemClock)
+}
+
+fn icon_selection_at(clock: &dyn clock::Clock) -> favicon::IconSelection {
+    let motd_data = get_motds();
+    if motd_data == Value::Null {
+        return favicon::IconSelection::Random;
+    }
 
-    Some(full_string)
+    let date = motd_date(&motd_data, clock);
+    let scheduled = active_entries(&motd_data, date).iter()
+        .find_map(|entry| entry["icon"].as_str().map(str::to_string));
+    if let Some(icon) = scheduled {
+        return favicon::IconSelection::Named(icon);
+    }
+
+    match motd_data["icon_rotation"].as_str() {
+        Some("daily") => favicon::IconSelection::Daily(
+            (clock.unix_seconds() + utc_offset(&motd_data)).div_euclid(86400)),
+        _ => favicon::IconSelection::Random,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedClock(i64);
+
+    impl clock::Clock for FixedClock {
+        fn unix_seconds(&self) -> i64 {
+            self.0
+        }
+    }
+
+    // 2026-12-25 03:00 UTC
+    const CHRISTMAS_MORNING_UTC: i64 = 1798167600;
+    // 2026-04-12 12:00 UTC
+    const APRIL_12_NOON_UTC: i64 = 1775995200;
+
+    fn schedule(timezone: &str) -> Value {
+        json!({
+            "timezone": timezone,
+            "line1": [{"text": "Purple Cello"}],
+            "line2": [{"text": "Regular"}],
+            "scheduled": [
+                {"date": "2026-12-25", "line2": {"text": "Merry Christmas"}},
+                {"from": "12-20", "to": "01-05", "weight": 3,
+                    "line1": {"text": "Happy Holidays"}},
+                {"birthday": {"name": "Steve", "date": "04-12"},
+                    "line2": {"text": "Happy birthday {birthday_name}"}},
+            ],
+        })
+    }
+
+    #[test]
+    fn time_zone_moves_the_date() {
+        let clock = FixedClock(CHRISTMAS_MORNING_UTC);
+        assert_eq!(motd_date(&schedule("UTC"), &clock).to_string(),
+            "2026-12-25");
+        assert_eq!(motd_date(&schedule("-07:00"), &clock).to_string(),
+            "2026-12-24");
+    }
+
+    #[test]
+    fn fixed_date_overrides_and_range_is_weighted() {
+        let motd_data = schedule("UTC");
+        let date = motd_date(&motd_data, &FixedClock(CHRISTMAS_MORNING_UTC));
+        let active = active_entries(&motd_data, date);
+        assert_eq!(active.len(), 2);
+
+        assert_eq!(candidate_lines(&motd_data, &active, "line2", None),
+            vec![(json!({"text": "Merry Christmas"}), 1)]);
+        assert_eq!(candidate_lines(&motd_data, &active, "line1", None), vec![
+            (json!({"text": "Purple Cello"}), 1),
+            (json!({"text": "Happy Holidays"}), 3),
+        ]);
+    }
+
+    #[test]
+    fn range_wraps_around_new_year() {
+        let motd_data = schedule("-07:00");
+        let date = motd_date(&motd_data, &FixedClock(CHRISTMAS_MORNING_UTC));
+        let active = active_entries(&motd_data, date);
+        assert_eq!(active.len(), 1);
+        assert!(is_active(&active[0],
+            clock::Date { year: 2027, month: 1, day: 3 }));
+    }
+
+    #[test]
+    fn birthday_fills_player_name() {
+        let motd_data = schedule("UTC");
+        let date = motd_date(&motd_data, &FixedClock(APRIL_12_NOON_UTC));
+        let active = active_entries(&motd_data, date);
+        assert_eq!(candidate_lines(&motd_data, &active, "line2", None),
+            vec![(json!({"text": "Happy birthday Steve"}), 1)]);
+    }
+
+    #[test]
+    fn known_visitors_get_personalised_lines() {
+        let motd_data = json!({
+            "line1": [{"text": "Purple Cello"}],
+            "visitor": {"line1": [{"text": "Welcome back, {player_name}"}]},
+            "players": {"Steve": {"line1": [{"text": "Hi Steve"}]}},
+        });
+        assert_eq!(candidate_lines(&motd_data, &[], "line1", None),
+            vec![(json!({"text": "Purple Cello"}), 1)]);
+        assert_eq!(candidate_lines(&motd_data, &[], "line1", Some("Alex")),
+            vec![(json!({"text": "Welcome back, {player_name}"}), 1)]);
+        assert_eq!(candidate_lines(&motd_data, &[], "line1", Some("Steve")),
+            vec![(json!({"text": "Hi Steve"}), 1)]);
+    }
+
+    #[test]
+    fn named_lines_need_a_name() {
+        let date = clock::Date { year: 2026, month: 4, day: 12 };
+        let mut context = MotdContext {
+            online: 0,
+            max: 20,
+            version: "1.21".to_string(),
+            backend_status: String::new(),
+            player_name: None,
+        };
+        let lines = vec![(json!({"text": "Welcome back, {player_name}"}), 1)];
+        assert_eq!(pick_line(lines.clone(), &context, date), None);
+
+        context.player_name = Some("Alex".to_string());
+        assert_eq!(pick_line(lines, &context, date),
+            Some(json!({"text": "Welcome back, Alex"})));
+    }
 }
@@ -0,0 +1,185 @@
+// Yeahbut October 2026
+
+// Packet ids the proxy needs to recognise or inject, by protocol version.
+// Only 1.19.4 (762) through 1.21 (767) are known.
+
+use std::ops::RangeInclusive;
+
+use purple_cello_mc_protocol::mc_types;
+
+pub const PROTOCOL_1_20_2: i32 = 764;
+pub const KNOWN_PROTOCOLS: RangeInclusive<i32> = 762..=767;
+
+// Versions sharing a layout for Login Start and Encryption Request
+fn login_layout(protocol: i32) -> Option<u8> {
+    match protocol {
+        // Login Start has an optional uuid
+        762 | 763 => Some(0),
+        764 | 765 => Some(1),
+        // Encryption Request says whether the client should authenticate
+        766 | 767 => Some(2),
+        _ => None,
+    }
+}
+
+// Login packets are read and written with the protocol crate's structs, so a
+// client must share the crate's login layout as well as have known ids
+pub fn is_supported(protocol: i32) -> bool {
+    login_layout(protocol).is_some() &&
+        login_layout(protocol) == login_layout(mc_types::VERSION_PROTOCOL)
+}
+
+pub fn has_configuration_state(protocol: i32) -> bool {
+    protocol >= PROTOCOL_1_20_2
+}
+
+pub mod login {
+    pub mod clientbound {
+        pub const DISCONNECT: i32 = 0x00;
+        pub const ENCRYPTION_REQUEST: i32 = 0x01;
+        pub const LOGIN_SUCCESS: i32 = 0x02;
+        pub const SET_COMPRESSION: i32 = 0x03;
+        pub const LOGIN_PLUGIN_REQUEST: i32 = 0x04;
+        pub const COOKIE_REQUEST: i32 = 0x05;
+    }
+
+    pub mod serverbound {
+        pub const LOGIN_PLUGIN_RESPONSE: i32 = 0x02;
+        pub const LOGIN_ACKNOWLEDGED: i32 = 0x03;
+        pub const COOKIE_RESPONSE: i32 = 0x04;
+    }
+}
+
+pub mod configuration {
+    pub mod clientbound {
+        pub fn disconnect(protocol: i32) -> Option<i32> {
+            match protocol {
+                764 | 765 => Some(0x01),
+                766 | 767 => Some(0x02),
+                _ => None,
+            }
+        }
+
+        pub fn finish_configuration(protocol: i32) -> Option<i32> {
+            match protocol {
+                764 | 765 => Some(0x02),
+                766 | 767 => Some(0x03),
+                _ => None,
+            }
+        }
+
+        pub fn store_cookie(protocol: i32) -> Option<i32> {
+            match protocol {
+                766 | 767 => Some(0x0A),
+                _ => None,
+            }
+        }
+
+        pub fn transfer(protocol: i32) -> Option<i32> {
+            match protocol {
+                766 | 767 => Some(0x0B),
+                _ => None,
+            }
+        }
+    }
+
+    pub mod serverbound {
+        pub fn acknowledge_finish_configuration(protocol: i32) -> Option<i32> {
+            match protocol {
+                764 | 765 => Some(0x02),
+                766 | 767 => Some(0x03),
+                _ => None,
+            }
+        }
+    }
+}
+
+pub mod play {
+    pub mod clientbound {
+        pub fn plugin_message(protocol: i32) -> Option<i32> {
+            match protocol {
+                762 | 763 => Some(0x17),
+                764 | 765 => Some(0x18),
+                766 | 767 => Some(0x19),
+                _ => None,
+            }
+        }
+
+        pub fn disconnect(protocol: i32) -> Option<i32> {
+            match protocol {
+                762 | 763 => Some(0x1A),
+                764 | 765 => Some(0x1B),
+                766 | 767 => Some(0x1D),
+                _ => None,
+            }
+        }
+
+        pub fn system_chat(protocol: i32) -> Option<i32> {
+            match protocol {
+                762 | 763 => Some(0x64),
+                764 => Some(0x67),
+                765 => Some(0x69),
+                766 | 767 => Some(0x6C),
+                _ => None,
+            }
+        }
+
+        pub fn start_configuration(protocol: i32) -> Option<i32> {
+            match protocol {
+                764 => Some(0x65),
+                765 => Some(0x67),
+                766 | 767 => Some(0x69),
+                _ => None,
+            }
+        }
+
+        pub fn store_cookie(protocol: i32) -> Option<i32> {
+            match protocol {
+                766 | 767 => Some(0x6B),
+                _ => None,
+            }
+        }
+
+        pub fn transfer(protocol: i32) -> Option<i32> {
+            match protocol {
+                766 | 767 => Some(0x73),
+                _ => None,
+            }
+        }
+    }
+
+    pub mod serverbound {
+        pub fn message_acknowledgement(protocol: i32) -> Option<i32> {
+            match protocol {
+                762..=767 => Some(0x03),
+                _ => None,
+            }
+        }
+
+        // Both the unsigned and signed variants start with the command
+        pub fn chat_command(protocol: i32) -> Vec<i32> {
+            match protocol {
+                762..=765 => vec![0x04],
+                766 | 767 => vec![0x04, 0x05],
+                _ => Vec::new(),
+            }
+        }
+
+        // The variant carrying signatures and last seen messages
+        pub fn signed_chat_command(protocol: i32) -> Option<i32> {
+            match protocol {
+                762..=765 => Some(0x04),
+                766 | 767 => Some(0x05),
+                _ => None,
+            }
+        }
+
+        pub fn acknowledge_configuration(protocol: i32) -> Option<i32> {
+            match protocol {
+                764 | 765 => Some(0x0B),
+                766 | 767 => Some(0x0C),
+                _ => None,
+            }
+        }
+    }
+}
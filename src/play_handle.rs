@@ -0,0 +1,616 @@
+// Yeahbut October 2026
+
+use std::mem;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+use tokio::net::{TcpStream, tcp::{OwnedReadHalf, OwnedWriteHalf}};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use purple_cello_mc_protocol::mc_types::{
+    self,
+    ProtocolConnection,
+    ProtocolRead,
+    ReadHaftProtocolConnection,
+};
+use serde_json::Value;
+
+use crate::chat;
+use crate::clock::{self, Clock};
+use crate::compression::{Frame, PacketWriter};
+use crate::connection_state::{State, StateTracker};
+use crate::commands::{self, CommandAction};
+use crate::listener;
+use crate::login_handle::{self, BackendLogin};
+use crate::packet_ids;
+use crate::raw_packet;
+use crate::session_cookie;
+use crate::sessions::{self, SessionInfo, SessionMessage, ByteCounters};
+use crate::wake;
+use crate::whitelist::Player;
+
+const BUNGEECORD_CHANNELS: [&str; 2] = ["BungeeCord", "bungeecord:main"];
+
+pub struct BackendLink {
+    pub conn: Box<ProtocolConnection<'static>>,
+    // Set once the backend sends Set Compression during login
+    pub compression: Option<i32>,
+    // Boxed so the connection's borrows stay valid when the link is moved
+    _socket: Box<(OwnedReadHalf, OwnedWriteHalf)>,
+}
+
+impl BackendLink {
+    pub fn new(backend_stream: TcpStream) -> Self {
+        let mut socket = Box::new(backend_stream.into_split());
+        let backend_reader: &'static mut OwnedReadHalf =
+            unsafe { mem::transmute(&mut socket.0) };
+        let backend_writer: &'static mut OwnedWriteHalf =
+            unsafe { mem::transmute(&mut socket.1) };
+        Self {
+            conn: Box::new(ProtocolConnection::new(
+                backend_reader,
+                backend_writer,
+            )),
+            compression: None,
+            _socket: socket,
+        }
+    }
+
+    pub async fn connect(
+        proxy_info: &listener::ProxyInfo,
+        client_addr: SocketAddr,
+    ) -> mc_types::Result<Self> {
+        let backend_stream =
+            proxy_info.backend.connect(Some(client_addr)).await?;
+        Ok(Self::new(backend_stream))
+    }
+}
+
+enum Event {
+    FromClient(Frame),
+    FromBackend(u32, Frame),
+    ClientClosed,
+    BackendClosed(u32),
+    // The login to another backend, run in its own task so traffic keeps
+    // flowing meanwhile
+    SwitchReady(String, Result<PendingSwitch, String>),
+}
+
+// A backend the player is moving to, logged in and waiting for the client
+// to acknowledge the configuration state
+struct PendingSwitch {
+    proxy_info: listener::ProxyInfo,
+    backend: BackendLink,
+}
+
+fn spawn_backend_reader(
+    backend_id: u32,
+    compression: Option<i32>,
+    mut server_read_conn: ReadHaftProtocolConnection<'static>,
+    events: mpsc::UnboundedSender<Event>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Ok(data) = server_read_conn.read_data().await {
+            let frame = match Frame::read(compression, data) {
+                Ok(frame) => frame,
+                Err(_) => break,
+            };
+            if events.send(Event::FromBackend(backend_id, frame)).is_err() {
+                return;
+            }
+        }
+        let _ = events.send(Event::BackendClosed(backend_id));
+    })
+}
+
+async fn send_message(
+    client_writer: &mut PacketWriter,
+    protocol: i32,
+    message: &Value,
+) -> mc_types::Result<()> {
+    if let Some(packet) = chat::system_chat_packet(protocol, message) {
+        client_writer.write(&packet).await?;
+    }
+    Ok(())
+}
+
+fn transfer_packet(
+    state: &StateTracker,
+    protocol: i32,
+    host: &str,
+    port: u16,
+) -> Option<Vec<u8>> {
+    let packet_id = match state.clientbound {
+        State::Play => packet_ids::play::clientbound::transfer(protocol)?,
+        State::Configuration =>
+            packet_ids::configuration::clientbound::transfer(protocol)?,
+        State::Login => return None,
+    };
+    let mut packet = raw_packet::new_packet(packet_id);
+    raw_packet::write_string(&mut packet, host);
+    raw_packet::write_var_int(&mut packet, port as i32);
+    Some(packet)
+}
+
+// Vouches for a player who authenticated with Mojang here, so a proxy sharing
+// the secret can skip authentication when they are transferred to it. Stored
+// after login and refreshed before a transfer.
+fn store_cookie_packet(
+    proxy_info: &listener::ProxyInfo,
+    state: &StateTracker,
+    protocol: i32,
+    player: &Player,
+    client_addr: SocketAddr,
+) -> Option<Vec<u8>> {
+    if !matches!(proxy_info.online_status, listener::OnlineStatus::Online) ||
+        !matches!(proxy_info.authentication_method,
+            listener::AuthenticationMethod::Mojang) {
+                return None;
+    }
+    let config = proxy_info.session_cookies.as_ref()?;
+    let packet_id = match state.clientbound {
+        State::Play => packet_ids::play::clientbound::store_cookie(protocol)?,
+        State::Configuration =>
+            packet_ids::configuration::clientbound::store_cookie(protocol)?,
+        State::Login => return None,
+    };
+    let cookie = session_cookie::create(
+        config,
+        &player.name,
+        player.player_uuid?,
+        client_addr.ip(),
+        clock::SystemClock.unix_seconds(),
+    );
+    let mut packet = raw_packet::new_packet(packet_id);
+    raw_packet::write_string(&mut packet, session_cookie::COOKIE_KEY);
+    raw_packet::write_var_int(&mut packet, cookie.len() as i32);
+    packet.extend(cookie);
+    Some(packet)
+}
+
+pub async fn handle_play<'a>(
+    mut proxy_info: listener::ProxyInfo,
+    player: Player,
+    protocol: i32,
+    client_addr: SocketAddr,
+    forge_marker: Option<String>,
+    mut client_conn: ProtocolConnection<'a>,
+    mut backend: BackendLink,
+) {
+    let client_conn: &mut ProtocolConnection<'static> =
+        unsafe { mem::transmute(&mut client_conn) };
+
+    let (client_write_conn, mut client_read_conn) =
+        client_conn.split_conn().expect(
+            "Error copying from client to backend");
+    let (server_write_conn, server_read_conn) =
+        backend.conn.split_conn().expect(
+            "Error copying from backend to client");
+
+    // The client's threshold is the listener's and never changes, each
+    // backend sets its own
+    let client_compression = proxy_info.compression_threshold;
+    let mut client_writer = PacketWriter {
+        conn: client_write_conn,
+        compression: client_compression,
+    };
+    let mut server_writer = PacketWriter {
+        conn: server_write_conn,
+        compression: backend.compression,
+    };
+
+    let (events, mut events_rx) = mpsc::unbounded_channel();
+
+    let client_events = events.clone();
+    let client_reader = tokio::spawn(async move {
+        while let Ok(data) = client_read_conn.read_data().await {
+            let frame = match Frame::read(client_compression, data) {
+                Ok(frame) => frame,
+                Err(_) => break,
+            };
+            if client_events.send(Event::FromClient(frame)).is_err() {
+                return;
+            }
+        }
+        let _ = client_events.send(Event::ClientClosed);
+    });
+
+    let mut backend_id: u32 = 0;
+    let mut backend_reader = spawn_backend_reader(
+        backend_id, backend.compression, server_read_conn, events.clone());
+    let mut pending: Option<PendingSwitch> = None;
+    let mut switching = false;
+    let mut cookie_stored = false;
+    let mut queued_chat: Vec<Value> = Vec::new();
+    let mut state = StateTracker::new(protocol);
+
+    let bytes = ByteCounters::default();
+    let (session, mut session_rx) = sessions::register(SessionInfo {
+        name: player.name.clone(),
+        player_uuid: player.player_uuid,
+        protocol,
+        client_addr,
+        listener: proxy_info.formatted_proxy_address(),
+        backend: proxy_info.backend.name.clone(),
+        connected_at: SystemTime::now(),
+        bytes: bytes.clone(),
+    });
+
+    wake::player_joined(&proxy_info.formatted_backend_address());
+
+    loop {
+        let event = tokio::select! {
+            event = events_rx.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            Some(message) = session_rx.recv() => {
+                match message {
+                    SessionMessage::Chat(message) => {
+                        // There is no chat while the client is configuring,
+                        // so it waits until they are back in play
+                        if state.clientbound == State::Play {
+                            let _ = send_message(
+                                &mut client_writer,
+                                protocol,
+                                &message,
+                            ).await;
+                        } else {
+                            queued_chat.push(message);
+                        }
+                    },
+                    SessionMessage::Kick(reason) => {
+                        println!("Kicking {}: {}", player.name, reason);
+                        let reason = chat::text(&reason, "red");
+                        let packet = match state.clientbound {
+                            State::Configuration => chat::
+                                configuration_disconnect_packet(
+                                    protocol, &reason),
+                            _ => chat::disconnect_packet(protocol, &reason),
+                        };
+                        if let Some(packet) = packet {
+                            let _ = client_writer.write(&packet).await;
+                        }
+                        break;
+                    },
+                    SessionMessage::Transfer(host, port) => {
+                        match transfer_packet(&state, protocol, &host, port) {
+                            Some(packet) => {
+                                println!("Transferring {} to {}:{}",
+                                    player.name, host, port);
+                                if let Some(cookie) = store_cookie_packet(
+                                    &proxy_info,
+                                    &state,
+                                    protocol,
+                                    &player,
+                                    client_addr,
+                                ) {
+                                    let _ = client_writer.write(&cookie).await;
+                                }
+                                let _ = client_writer.write(&packet).await;
+                                break;
+                            },
+                            None => println!(
+                                "Can't transfer {}, their client is too old",
+                                player.name),
+                        }
+                    },
+                }
+                continue;
+            },
+        };
+        let mut switch_target: Option<String> = None;
+
+        match event {
+            Event::FromClient(frame) => {
+                state.client_packet(&frame.packet);
+
+                // Configuration packets can be sent once the client has
+                // acknowledged the login
+                if !cookie_stored && state.serverbound == State::Configuration {
+                    cookie_stored = true;
+                    if let Some(cookie) = store_cookie_packet(
+                        &proxy_info,
+                        &state,
+                        protocol,
+                        &player,
+                        client_addr,
+                    ) {
+                        if client_writer.write(&cookie).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+
+                if let Some(switch) = pending.take() {
+                    // Play packets sent before the acknowledgement were meant
+                    // for the old backend, which is still connected
+                    if state.serverbound != State::Configuration {
+                        pending = Some(switch);
+                        bytes.to_backend.fetch_add(
+                            frame.packet.len() as u64, Ordering::Relaxed);
+                        if server_writer.relay(frame).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+
+                    let mut switch = switch;
+                    let (new_write_conn, new_read_conn) =
+                        match switch.backend.conn.split_conn() {
+                            Ok(halves) => halves,
+                            Err(_) => break,
+                        };
+                    server_writer = PacketWriter {
+                        conn: new_write_conn,
+                        compression: switch.backend.compression,
+                    };
+                    let login_acknowledged = raw_packet::new_packet(
+                        packet_ids::login::serverbound::LOGIN_ACKNOWLEDGED);
+                    if server_writer.write(&login_acknowledged).await.is_err() {
+                        break;
+                    }
+
+                    wake::player_left(&proxy_info.formatted_backend_address());
+                    backend = switch.backend;
+                    proxy_info = switch.proxy_info;
+                    session.set_backend(&proxy_info.backend.name);
+                    wake::player_joined(
+                        &proxy_info.formatted_backend_address());
+
+                    backend_id += 1;
+                    backend_reader = spawn_backend_reader(
+                        backend_id,
+                        backend.compression,
+                        new_read_conn,
+                        events.clone(),
+                    );
+                    println!("{} switched to {}",
+                        player.name, proxy_info.backend.name);
+                    continue;
+                }
+
+                let command_line = match state.serverbound {
+                    State::Play => commands::intercept(
+                        &proxy_info.commands, protocol, &frame.packet),
+                    _ => None,
+                };
+                if let Some(command_line) = command_line {
+                        if let Some(acknowledgement) =
+                            commands::dropped_acknowledgement(
+                                protocol, &frame.packet) {
+                                    if server_writer.write(&acknowledgement)
+                                        .await.is_err() {
+                                            break;
+                                    }
+                        }
+                        for action in commands::run(
+                            &proxy_info, &player, &command_line) {
+                                match action {
+                                    CommandAction::Reply(message) => {
+                                        let _ = send_message(
+                                            &mut client_writer,
+                                            protocol,
+                                            &message,
+                                        ).await;
+                                    },
+                                    CommandAction::Switch(target) =>
+                                        switch_target = Some(target),
+                                }
+                        }
+                } else {
+                    bytes.to_backend.fetch_add(
+                        frame.packet.len() as u64, Ordering::Relaxed);
+                    if server_writer.relay(frame).await.is_err() {
+                        break;
+                    }
+                }
+            },
+            Event::FromBackend(id, frame) => {
+                if id != backend_id || pending.is_some() {
+                    continue;
+                }
+
+                let in_play = state.clientbound == State::Play;
+                state.backend_packet(&frame.packet);
+
+                let target = if in_play {
+                    connect_request(protocol, &frame.packet)
+                } else {
+                    None
+                };
+                if let Some(target) = target {
+                    switch_target = Some(target);
+                } else {
+                    bytes.to_client.fetch_add(
+                        frame.packet.len() as u64, Ordering::Relaxed);
+                    if client_writer.relay(frame).await.is_err() {
+                        break;
+                    }
+                }
+
+                if state.clientbound == State::Play {
+                    for message in queued_chat.drain(..) {
+                        let _ = send_message(
+                            &mut client_writer,
+                            protocol,
+                            &message,
+                        ).await;
+                    }
+                }
+            },
+            Event::ClientClosed => break,
+            Event::BackendClosed(id) => {
+                if id == backend_id && pending.is_none() {
+                    break;
+                }
+            },
+            Event::SwitchReady(target, switch) => {
+                switching = false;
+                match switch {
+                    Ok(_) if !state.in_play() => println!(
+                        "Dropping switch of {} to {}, they are configuring",
+                        player.name, target),
+                    Ok(switch) => {
+                        let start_configuration = raw_packet::new_packet(
+                            packet_ids::play::clientbound::
+                                start_configuration(protocol)
+                                .unwrap_or_default());
+                        if client_writer.write(&start_configuration)
+                            .await.is_err() {
+                                break;
+                        }
+                        state.backend_packet(&start_configuration);
+                        backend_reader.abort();
+                        let _ = (&mut backend_reader).await;
+                        pending = Some(switch);
+                    },
+                    Err(msg) => {
+                        println!("Switching {} to {} failed: {}",
+                            player.name, target, msg);
+                        if send_message(
+                            &mut client_writer,
+                            protocol,
+                            &chat::text(&msg, "red"),
+                        ).await.is_err() {
+                            break;
+                        }
+                    },
+                }
+            },
+        }
+
+        if let Some(target) = switch_target {
+            if !state.in_play() {
+                println!("Not switching {} to {} while configuring",
+                    player.name, target);
+                continue;
+            }
+            if switching || pending.is_some() {
+                println!("{} is already switching servers", player.name);
+                continue;
+            }
+            switching = true;
+            let switch_info = proxy_info.clone();
+            let switch_player = player.clone();
+            let modded = forge_marker.is_some();
+            let switch_events = events.clone();
+            tokio::spawn(async move {
+                let switch = begin_switch(
+                    &switch_info,
+                    &switch_player,
+                    protocol,
+                    client_addr,
+                    modded,
+                    &target,
+                ).await;
+                let _ = switch_events.send(Event::SwitchReady(target, switch));
+            });
+        }
+    }
+
+    wake::player_left(&proxy_info.formatted_backend_address());
+
+    client_reader.abort();
+    backend_reader.abort();
+    let _ = client_reader.await;
+    let _ = backend_reader.await;
+}
+
+// Logs the player into another backend so the client can be moved to it
+async fn begin_switch(
+    proxy_info: &listener::ProxyInfo,
+    player: &Player,
+    protocol: i32,
+    client_addr: SocketAddr,
+    modded: bool,
+    target: &str,
+) -> Result<PendingSwitch, String> {
+    if !packet_ids::has_configuration_state(protocol) ||
+        packet_ids::play::clientbound::start_configuration(protocol)
+            .is_none() {
+                return Err(
+                    "Server switching requires 1.20.2 or newer".to_string());
+    }
+
+    let backend = match proxy_info.find_backend(target) {
+        Some(backend) => backend,
+        None => return Err(format!("Unknown server: {}", target)),
+    };
+    if backend.name == proxy_info.backend.name {
+        return Err(format!("Already connected to {}", backend.name));
+    }
+    if !backend.protocols.accepts(protocol) {
+        return Err(format!("{} does not support your version", backend.name));
+    }
+    // The client is past login, so Forge's login handshake can't be relayed
+    // to the new backend
+    if modded {
+        return Err("Modded clients can't switch servers, please reconnect \
+to join another one".to_string());
+    }
+
+    let mut new_info = proxy_info.clone();
+    new_info.backend = backend;
+
+    let mut new_backend =
+        match BackendLink::connect(&new_info, client_addr).await {
+            Ok(new_backend) => new_backend,
+            Err(_) => return Err(
+                format!("Could not connect to {}", new_info.backend.name)),
+    };
+    match login_handle::backend_login(
+        &new_info.backend,
+        protocol,
+        player,
+        None,
+        None,
+        &mut new_backend,
+    ).await {
+        Ok(BackendLogin::Success(_)) => {},
+        Ok(BackendLogin::Disconnected(packet)) => return Err(format!(
+            "Disconnected from {}: {}", new_info.backend.name,
+            login_handle::disconnect_reason(&packet))),
+        Ok(BackendLogin::Refused(_)) => return Err(
+            format!("{} is misconfigured", new_info.backend.name)),
+        Err(e) => return Err(format!(
+            "Could not log into {}: {}", new_info.backend.name, e)),
+    }
+
+    Ok(PendingSwitch {
+        proxy_info: new_info,
+        backend: new_backend,
+    })
+}
+
+// Java's DataOutput::writeUTF, as used by BungeeCord plugin messages
+fn read_java_utf(data: &[u8], index: &mut usize) -> Option<String> {
+    let length = u16::from_be_bytes(
+        [*data.get(*index)?, *data.get(*index + 1)?]) as usize;
+    let start = *index + 2;
+    let string = String::from_utf8(
+        data.get(start..start + length)?.to_vec()).ok()?;
+    *index = start + length;
+    Some(string)
+}
+
+// Backend plugins ask for a player to be moved with a BungeeCord "Connect"
+// plugin message
+fn connect_request(protocol: i32, data: &[u8]) -> Option<String> {
+    let mut index = 0;
+    let packet_id = raw_packet::read_var_int(data, &mut index)?;
+    if packet_id != packet_ids::play::clientbound::plugin_message(protocol)? {
+        return None;
+    }
+
+    let channel = raw_packet::read_string(data, &mut index)?;
+    if !BUNGEECORD_CHANNELS.contains(&channel.as_str()) {
+        return None;
+    }
+
+    if read_java_utf(data, &mut index)? != "Connect" {
+        return None;
+    }
+    read_java_utf(data, &mut index)
+}
@@ -0,0 +1,379 @@
+// Yeahbut October 2026
+
+// HAProxy PROXY protocol headers, sent ahead of the Minecraft traffic by a
+// load balancer so the proxy learns the real client address, and by the proxy
+// to backends that want it. Version 1 is a single text line, version 2 a
+// binary header after a 12 byte signature.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+// Including the CRLF
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: [u8; 12] =
+    [0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A];
+const V2_VERSION: u8 = 0x20;
+const V2_COMMAND_LOCAL: u8 = 0x00;
+const V2_COMMAND_PROXY: u8 = 0x01;
+const V2_TCP4: u8 = 0x11;
+const V2_TCP6: u8 = 0x21;
+const V2_UNSPEC: u8 = 0x00;
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// An address block such as 10.0.0.0/8, or a single address
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+// IPv4 peers may show up as IPv4-mapped addresses on a dual stack socket
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => addr,
+        },
+        IpAddr::V4(_) => addr,
+    }
+}
+
+impl Cidr {
+    pub fn parse(cidr: &str) -> Option<Self> {
+        let (addr, prefix) = match cidr.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (cidr, None),
+        };
+        let addr = canonical(addr.trim().parse().ok()?);
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().ok()?,
+            None => max,
+        };
+        if prefix > max {
+            return None;
+        }
+        Some(Self { addr, prefix })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let (network, addr, bits) = match (self.addr, canonical(addr)) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => (
+                u32::from(network) as u128, u32::from(addr) as u128, 32),
+            (IpAddr::V6(network), IpAddr::V6(addr)) =>
+                (u128::from(network), u128::from(addr), 128),
+            _ => return false,
+        };
+        if self.prefix == 0 {
+            return true;
+        }
+        let shift = bits - self.prefix as u32;
+        network >> shift == addr >> shift
+    }
+}
+
+#[derive(Clone)]
+pub struct InboundProxyProtocol {
+    // Peers that must open with a PROXY header, usually the load balancers.
+    // Anyone else is treated as a direct client.
+    pub trusted: Vec<Cidr>,
+}
+
+impl InboundProxyProtocol {
+    pub fn is_trusted(&self, peer: IpAddr) -> bool {
+        self.trusted.iter().any(|cidr| cidr.contains(peer))
+    }
+}
+
+// None for UNKNOWN, when the balancer doesn't know the client either
+pub fn parse_v1(line: &str) -> io::Result<Option<SocketAddr>> {
+    let fields: Vec<&str> = line.trim_end_matches("\r\n")
+        .split(' ')
+        .collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _destination, port, _] => {
+            let ip: IpAddr = source.parse()
+                .map_err(|_| invalid("bad PROXY source address"))?;
+            let port: u16 = port.parse()
+                .map_err(|_| invalid("bad PROXY source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        },
+        _ => Err(invalid("malformed PROXY v1 header")),
+    }
+}
+
+// Takes the two bytes after the signature and the address block. None for
+// LOCAL connections, such as the balancer's own health checks, and for
+// address families other than TCP.
+pub fn parse_v2(
+    version_command: u8,
+    family: u8,
+    addresses: &[u8],
+) -> io::Result<Option<SocketAddr>> {
+    if version_command & 0xF0 != V2_VERSION {
+        return Err(invalid("unsupported PROXY version"));
+    }
+    match version_command & 0x0F {
+        V2_COMMAND_LOCAL => return Ok(None),
+        V2_COMMAND_PROXY => {},
+        _ => return Err(invalid("unsupported PROXY command")),
+    }
+
+    let short = || invalid("PROXY v2 address block too short");
+    match family {
+        V2_TCP4 => {
+            let block = addresses.get(..12).ok_or_else(short)?;
+            let ip = Ipv4Addr::new(block[0], block[1], block[2], block[3]);
+            let port = u16::from_be_bytes([block[8], block[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        },
+        V2_TCP6 => {
+            let block = addresses.get(..36).ok_or_else(short)?;
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&block[..16]);
+            let port = u16::from_be_bytes([block[32], block[33]]);
+            Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port)))
+        },
+        _ => Ok(None),
+    }
+}
+
+async fn read_header(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut first = [0; 1];
+    stream.read_exact(&mut first).await?;
+
+    if first[0] == V1_PREFIX[0] {
+        let mut line = first.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() >= V1_MAX_LENGTH {
+                return Err(invalid("PROXY v1 header too long"));
+            }
+            line.push(stream.read_u8().await?);
+        }
+        if !line.starts_with(V1_PREFIX) {
+            return Err(invalid("missing PROXY header"));
+        }
+        let line = String::from_utf8(line)
+            .map_err(|_| invalid("PROXY v1 header is not ASCII"))?;
+        return parse_v1(&line);
+    }
+
+    if first[0] == V2_SIGNATURE[0] {
+        let mut header = [0; 15];
+        stream.read_exact(&mut header).await?;
+        if header[..11] != V2_SIGNATURE[1..] {
+            return Err(invalid("bad PROXY v2 signature"));
+        }
+        let length = u16::from_be_bytes([header[13], header[14]]) as usize;
+        let mut addresses = vec![0; length];
+        stream.read_exact(&mut addresses).await?;
+        return parse_v2(header[11], header[12], &addresses);
+    }
+
+    Err(invalid("missing PROXY header"))
+}
+
+// The address to treat as the client's. Trusted peers must send a header,
+// which is consumed here so the Minecraft traffic starts right after it.
+pub async fn accept(
+    config: &Option<InboundProxyProtocol>,
+    stream: &mut TcpStream,
+    peer: SocketAddr,
+) -> io::Result<SocketAddr> {
+    match config {
+        Some(config) if config.is_trusted(peer.ip()) => {
+            let source = timeout(HEADER_TIMEOUT, read_header(stream)).await
+                .map_err(|_| io::Error::new(
+                    io::ErrorKind::TimedOut, "no PROXY header"))??;
+            Ok(source.unwrap_or(peer))
+        },
+        _ => Ok(peer),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProxyProtocolVersion {
+    V1,
+    V2,
+}
+
+// Both ends of a header must be the same family, so IPv4 is mapped into IPv6
+// when the other end is IPv6
+fn same_family(
+    source: SocketAddr,
+    destination: SocketAddr,
+) -> (SocketAddr, SocketAddr) {
+    let source = SocketAddr::new(canonical(source.ip()), source.port());
+    let destination =
+        SocketAddr::new(canonical(destination.ip()), destination.port());
+    let as_v6 = |addr: SocketAddr| match addr.ip() {
+        IpAddr::V4(v4) => SocketAddr::new(
+            IpAddr::V6(v4.to_ipv6_mapped()), addr.port()),
+        IpAddr::V6(_) => addr,
+    };
+    if source.is_ipv4() == destination.is_ipv4() {
+        (source, destination)
+    } else {
+        (as_v6(source), as_v6(destination))
+    }
+}
+
+// A header for a connection from source to destination. Without a source the
+// header says the connection is the proxy's own, e.g. a status check.
+pub fn header(
+    version: ProxyProtocolVersion,
+    source: Option<SocketAddr>,
+    destination: SocketAddr,
+) -> Vec<u8> {
+    let addresses = source.map(|source| same_family(source, destination));
+    match version {
+        ProxyProtocolVersion::V1 => match addresses {
+            Some((source, destination)) => format!(
+                "PROXY {} {} {} {} {}\r\n",
+                if source.is_ipv4() { "TCP4" } else { "TCP6" },
+                source.ip(),
+                destination.ip(),
+                source.port(),
+                destination.port(),
+            ).into_bytes(),
+            None => b"PROXY UNKNOWN\r\n".to_vec(),
+        },
+        ProxyProtocolVersion::V2 => {
+            let mut data = V2_SIGNATURE.to_vec();
+            let mut block = Vec::new();
+            match addresses {
+                Some((source, destination)) => {
+                    data.push(V2_VERSION | V2_COMMAND_PROXY);
+                    match (source.ip(), destination.ip()) {
+                        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                            data.push(V2_TCP4);
+                            block.extend_from_slice(&src.octets());
+                            block.extend_from_slice(&dst.octets());
+                        },
+                        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                            data.push(V2_TCP6);
+                            block.extend_from_slice(&src.octets());
+                            block.extend_from_slice(&dst.octets());
+                        },
+                        // same_family rules this out
+                        _ => unreachable!(),
+                    }
+                    block.extend_from_slice(&source.port().to_be_bytes());
+                    block.extend_from_slice(&destination.port().to_be_bytes());
+                },
+                None => {
+                    data.push(V2_VERSION | V2_COMMAND_LOCAL);
+                    data.push(V2_UNSPEC);
+                },
+            }
+            data.extend_from_slice(&(block.len() as u16).to_be_bytes());
+            data.extend(block);
+            data
+        },
+    }
+}
+
+// Written right after connecting to a backend, before the handshake
+pub async fn send_header(
+    version: ProxyProtocolVersion,
+    stream: &mut TcpStream,
+    source: Option<SocketAddr>,
+) -> io::Result<()> {
+    let destination = stream.peer_addr()?;
+    stream.write_all(&header(version, source, destination)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cidr_matching() {
+        let cidr = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(cidr.contains("10.1.200.3".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(!cidr.contains("10.2.0.1".parse().unwrap()));
+        assert!(!cidr.contains("::1".parse().unwrap()));
+
+        let single = Cidr::parse("192.168.0.5").unwrap();
+        assert!(single.contains("192.168.0.5".parse().unwrap()));
+        assert!(!single.contains("192.168.0.6".parse().unwrap()));
+
+        assert!(Cidr::parse("0.0.0.0/0").unwrap()
+            .contains("8.8.8.8".parse().unwrap()));
+        assert!(Cidr::parse("fd00::/8").unwrap()
+            .contains("fd12::1".parse().unwrap()));
+        assert_eq!(Cidr::parse("10.0.0.0/33"), None);
+    }
+
+    #[test]
+    fn v1_header() {
+        assert_eq!(
+            parse_v1("PROXY TCP4 203.0.113.7 10.0.0.2 51234 25565\r\n")
+                .unwrap(),
+            Some("203.0.113.7:51234".parse().unwrap()),
+        );
+        assert_eq!(
+            parse_v1("PROXY TCP6 2001:db8::1 2001:db8::2 4000 25565\r\n")
+                .unwrap(),
+            Some("[2001:db8::1]:4000".parse().unwrap()),
+        );
+        assert_eq!(parse_v1("PROXY UNKNOWN\r\n").unwrap(), None);
+        assert!(parse_v1("PROXY TCP4 nonsense\r\n").is_err());
+    }
+
+    #[test]
+    fn v2_header() {
+        let addresses = [
+            203, 0, 113, 7,
+            10, 0, 0, 2,
+            0xC8, 0x22,
+            0x63, 0xDD,
+        ];
+        assert_eq!(
+            parse_v2(0x21, V2_TCP4, &addresses).unwrap(),
+            Some("203.0.113.7:51234".parse().unwrap()),
+        );
+        assert_eq!(parse_v2(0x20, 0x00, &[]).unwrap(), None);
+        assert!(parse_v2(0x21, V2_TCP4, &addresses[..6]).is_err());
+        assert!(parse_v2(0x11, V2_TCP4, &addresses).is_err());
+    }
+
+    #[test]
+    fn written_headers_parse() {
+        let client: SocketAddr = "203.0.113.7:51234".parse().unwrap();
+        let backend: SocketAddr = "10.0.0.2:25565".parse().unwrap();
+
+        let v1 = header(ProxyProtocolVersion::V1, Some(client), backend);
+        assert_eq!(v1, b"PROXY TCP4 203.0.113.7 10.0.0.2 51234 25565\r\n");
+        assert_eq!(parse_v1(std::str::from_utf8(&v1).unwrap()).unwrap(),
+            Some(client));
+        assert_eq!(header(ProxyProtocolVersion::V1, None, backend),
+            b"PROXY UNKNOWN\r\n");
+
+        let v2 = header(ProxyProtocolVersion::V2, Some(client), backend);
+        assert_eq!(v2[..12], V2_SIGNATURE);
+        assert_eq!(u16::from_be_bytes([v2[14], v2[15]]), 12);
+        assert_eq!(parse_v2(v2[12], v2[13], &v2[16..]).unwrap(), Some(client));
+
+        // An IPv6 client reaching an IPv4 backend
+        let client: SocketAddr = "[2001:db8::1]:4000".parse().unwrap();
+        let v2 = header(ProxyProtocolVersion::V2, Some(client), backend);
+        assert_eq!(v2[13], V2_TCP6);
+        assert_eq!(parse_v2(v2[12], v2[13], &v2[16..]).unwrap(), Some(client));
+
+        let local = header(ProxyProtocolVersion::V2, None, backend);
+        assert_eq!(parse_v2(local[12], local[13], &local[16..]).unwrap(),
+            None);
+    }
+}
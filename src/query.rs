@@ -0,0 +1,332 @@
+// Yeahbut October 2026
+
+// The GameSpy4 "query" protocol used by server list websites, answered over
+// UDP next to a TCP listener. Stat requests need a challenge token that was
+// sent to the requesting address, so the listener can't be used to reflect
+// large replies at someone else.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tokio::net::UdpSocket;
+use lazy_static::lazy_static;
+
+use purple_cello_mc_protocol::mc_types;
+
+use crate::chat;
+use crate::listener;
+use crate::motd::motd;
+use crate::sessions;
+use crate::status_cache;
+use crate::status_handle;
+
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+const TYPE_HANDSHAKE: u8 = 0x09;
+const TYPE_STAT: u8 = 0x00;
+// Only the low four bits of each session id byte are used
+const SESSION_MASK: i32 = 0x0F0F0F0F;
+// Full stat requests pad the token with four bytes
+const FULL_STAT_LENGTH: usize = 15;
+
+// Tokens stay valid for between one and two of these
+const TOKEN_ROTATION: Duration = Duration::from_secs(30);
+
+#[derive(Copy, Clone)]
+struct TokenSecrets {
+    current: u64,
+    previous: u64,
+    rotated: Instant,
+}
+
+lazy_static! {
+    static ref SECRETS: Arc<Mutex<TokenSecrets>> =
+        Arc::new(Mutex::new(TokenSecrets {
+            current: rand::thread_rng().gen(),
+            previous: rand::thread_rng().gen(),
+            rotated: Instant::now(),
+        }));
+}
+
+fn token_for(secret: u64, addr: SocketAddr) -> i32 {
+    let mut hasher = DefaultHasher::new();
+    secret.hash(&mut hasher);
+    addr.hash(&mut hasher);
+    // Some clients parse the token as unsigned
+    hasher.finish() as i32 & i32::MAX
+}
+
+fn rotate_secrets() -> TokenSecrets {
+    let mut secrets = SECRETS.lock().unwrap();
+    if secrets.rotated.elapsed() >= TOKEN_ROTATION {
+        secrets.previous = secrets.current;
+        secrets.current = rand::thread_rng().gen();
+        secrets.rotated = Instant::now();
+    }
+    *secrets
+}
+
+fn challenge_token(addr: SocketAddr) -> i32 {
+    token_for(rotate_secrets().current, addr)
+}
+
+fn valid_token(addr: SocketAddr, token: i32) -> bool {
+    let secrets = rotate_secrets();
+    token == token_for(secrets.current, addr) ||
+        token == token_for(secrets.previous, addr)
+}
+
+fn read_i32(data: &[u8], index: usize) -> Option<i32> {
+    let bytes = data.get(index..index + 4)?;
+    Some(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn write_string(data: &mut Vec<u8>, string: &str) {
+    data.extend_from_slice(string.as_bytes());
+    data.push(0);
+}
+
+struct QueryStatus {
+    motd: String,
+    version: String,
+    online: i32,
+    max: i32,
+    players: Vec<String>,
+}
+
+fn query_status(
+    proxy_info: &listener::ProxyInfo,
+    client_addr: SocketAddr,
+) -> QueryStatus {
+    let backends = proxy_info.status_backends();
+    let players: Vec<String> = sessions::online_players().into_iter()
+        .filter(|session| backends.iter()
+            .any(|backend| backend.name == session.backend))
+        .map(|session| session.name)
+        .collect();
+
+    match status_cache::aggregate_status(&backends) {
+        Some(aggregate) => QueryStatus {
+            motd: chat::legacy_text(&motd(
+                &status_handle::motd_context(&aggregate, client_addr),
+            )).replace('\n', " "),
+            version: aggregate.status.version_name.clone(),
+            online: aggregate.status.online,
+            max: aggregate.status.max,
+            players,
+        },
+        None => QueryStatus {
+            motd: String::new(),
+            version: mc_types::VERSION_NAME.to_string(),
+            online: players.len() as i32,
+            max: 0,
+            players,
+        },
+    }
+}
+
+fn basic_stat(status: &QueryStatus, host_ip: &str, host_port: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+    write_string(&mut data, &status.motd);
+    write_string(&mut data, "SMP");
+    write_string(&mut data, "world");
+    write_string(&mut data, &status.online.to_string());
+    write_string(&mut data, &status.max.to_string());
+    // The only little endian field in the protocol
+    data.extend_from_slice(&host_port.to_le_bytes());
+    write_string(&mut data, host_ip);
+    data
+}
+
+fn full_stat(status: &QueryStatus, host_ip: &str, host_port: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(b"splitnum\0\x80\0");
+    for (key, value) in [
+        ("hostname", status.motd.clone()),
+        ("gametype", "SMP".to_string()),
+        ("game_id", "MINECRAFT".to_string()),
+        ("version", status.version.clone()),
+        ("plugins", String::new()),
+        ("map", "world".to_string()),
+        ("numplayers", status.online.to_string()),
+        ("maxplayers", status.max.to_string()),
+        ("hostport", host_port.to_string()),
+        ("hostip", host_ip.to_string()),
+    ] {
+        write_string(&mut data, key);
+        write_string(&mut data, &value);
+    }
+    data.push(0);
+    data.extend_from_slice(b"\x01player_\0\0");
+    for player in &status.players {
+        write_string(&mut data, player);
+    }
+    data.push(0);
+    data
+}
+
+// The stat body is built by the caller, told whether a full stat was asked for
+fn respond(
+    request: &[u8],
+    client_addr: SocketAddr,
+    stat: impl FnOnce(bool) -> Vec<u8>,
+) -> Option<Vec<u8>> {
+    if request.get(0..2)? != MAGIC {
+        return None;
+    }
+    let packet_type = *request.get(2)?;
+    let session_id = read_i32(request, 3)? & SESSION_MASK;
+
+    let mut response = vec![packet_type];
+    response.extend_from_slice(&session_id.to_be_bytes());
+
+    match packet_type {
+        TYPE_HANDSHAKE => {
+            write_string(&mut response,
+                &challenge_token(client_addr).to_string());
+        },
+        TYPE_STAT => {
+            if !valid_token(client_addr, read_i32(request, 7)?) {
+                return None;
+            }
+            response.extend(stat(request.len() >= FULL_STAT_LENGTH));
+        },
+        _ => return None,
+    }
+    Some(response)
+}
+
+pub async fn query_listener(proxy_info: listener::ProxyInfo, port: u16) {
+    let address = format!("{}:{}", proxy_info.proxy_addr, port);
+    let socket = match UdpSocket::bind(&address).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            println!("Failed to bind query listener on {}: {}", address, e);
+            return;
+        },
+    };
+    println!("Query listening on {}", address);
+
+    let mut buffer = [0; 1460];
+    loop {
+        let (length, client_addr) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                println!("Error receiving query: {}", e);
+                continue;
+            },
+        };
+        let stat = |full| {
+            let status = query_status(&proxy_info, client_addr);
+            let (host_ip, host_port) =
+                (&proxy_info.proxy_addr, proxy_info.proxy_port);
+            if full {
+                full_stat(&status, host_ip, host_port)
+            } else {
+                basic_stat(&status, host_ip, host_port)
+            }
+        };
+        if let Some(response) = respond(&buffer[..length], client_addr, stat) {
+                if let Err(e) = socket.send_to(&response, client_addr).await {
+                    println!("Error sending query response: {}", e);
+                }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> QueryStatus {
+        QueryStatus {
+            motd: "Hi".to_string(),
+            version: "1.21".to_string(),
+            online: 2,
+            max: 20,
+            players: vec!["Steve".to_string(), "Alex".to_string()],
+        }
+    }
+
+    fn request(packet_type: u8, session_id: i32, payload: &[u8]) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.push(packet_type);
+        data.extend_from_slice(&session_id.to_be_bytes());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn no_stat(_: bool) -> Vec<u8> {
+        panic!("stat built for a bad request")
+    }
+
+    #[test]
+    fn challenge_round_trip() {
+        let client: SocketAddr = "203.0.113.7:40000".parse().unwrap();
+        let other: SocketAddr = "203.0.113.8:40000".parse().unwrap();
+
+        let handshake = respond(
+            &request(TYPE_HANDSHAKE, 0x01020304, &[]), client, no_stat)
+            .unwrap();
+        // The session id keeps only the low four bits of each byte
+        assert_eq!(handshake[..5], [TYPE_HANDSHAKE, 0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(handshake.last(), Some(&0));
+        let token: i32 = std::str::from_utf8(&handshake[5..handshake.len() - 1])
+            .unwrap().parse().unwrap();
+        assert!(token >= 0);
+
+        let basic = request(TYPE_STAT, 1, &token.to_be_bytes());
+        assert_eq!(respond(&basic, client, |full| {
+            assert!(!full);
+            b"basic".to_vec()
+        }), Some([&[TYPE_STAT, 0, 0, 0, 1][..], b"basic"].concat()));
+
+        let mut full_payload = token.to_be_bytes().to_vec();
+        full_payload.extend_from_slice(&[0; 4]);
+        let full = request(TYPE_STAT, 1, &full_payload);
+        assert_eq!(respond(&full, client, |full| {
+            assert!(full);
+            b"full".to_vec()
+        }), Some([&[TYPE_STAT, 0, 0, 0, 1][..], b"full"].concat()));
+
+        // Tokens only work from the address they were sent to
+        assert_eq!(respond(&basic, other, no_stat), None);
+        assert_eq!(respond(&request(TYPE_STAT, 1, &(token ^ 1).to_be_bytes()),
+            client, no_stat), None);
+        assert_eq!(respond(&[0xFE, 0xFC, TYPE_HANDSHAKE, 0, 0, 0, 1],
+            client, no_stat), None);
+    }
+
+    #[test]
+    fn basic_stat_layout() {
+        let mut expected = b"Hi\0SMP\0world\02\020\0".to_vec();
+        expected.extend_from_slice(&[0xDD, 0x63]);
+        expected.extend_from_slice(b"127.0.0.1\0");
+        assert_eq!(basic_stat(&status(), "127.0.0.1", 25565), expected);
+    }
+
+    #[test]
+    fn full_stat_layout() {
+        let expected = [
+            &b"splitnum\0\x80\0"[..],
+            b"hostname\0Hi\0",
+            b"gametype\0SMP\0",
+            b"game_id\0MINECRAFT\0",
+            b"version\01.21\0",
+            b"plugins\0\0",
+            b"map\0world\0",
+            b"numplayers\02\0",
+            b"maxplayers\020\0",
+            b"hostport\025565\0",
+            b"hostip\0127.0.0.1\0",
+            b"\0",
+            b"\x01player_\0\0",
+            b"Steve\0Alex\0",
+            b"\0",
+        ].concat();
+        assert_eq!(full_stat(&status(), "127.0.0.1", 25565), expected);
+    }
+}
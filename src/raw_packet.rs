@@ -0,0 +1,55 @@
+// Yeahbut October 2026
+
+// Helpers for looking inside packets the proxy otherwise forwards untouched.
+// Packets are the uncompressed, decrypted packet id followed by the body.
+
+pub fn read_var_int(data: &[u8], index: &mut usize) -> Option<i32> {
+    let mut value: i32 = 0;
+    for position in 0..5 {
+        let byte = *data.get(*index)?;
+        *index += 1;
+        value |= ((byte & 0x7F) as i32) << (7 * position);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+pub fn write_var_int(data: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7F == 0 {
+            data.push(value as u8);
+            return;
+        }
+        data.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+pub fn read_string(data: &[u8], index: &mut usize) -> Option<String> {
+    let length = read_var_int(data, index)?;
+    if length < 0 {
+        return None;
+    }
+    let end = index.checked_add(length as usize)?;
+    let string = String::from_utf8(data.get(*index..end)?.to_vec()).ok()?;
+    *index = end;
+    Some(string)
+}
+
+pub fn write_string(data: &mut Vec<u8>, string: &str) {
+    write_var_int(data, string.len() as i32);
+    data.extend_from_slice(string.as_bytes());
+}
+
+pub fn packet_id(data: &[u8]) -> Option<i32> {
+    read_var_int(data, &mut 0)
+}
+
+pub fn new_packet(packet_id: i32) -> Vec<u8> {
+    let mut data = Vec::new();
+    write_var_int(&mut data, packet_id);
+    data
+}
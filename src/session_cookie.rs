@@ -0,0 +1,146 @@
+// Yeahbut October 2026
+
+// Signed cookies (1.20.5+) that vouch for a player who authenticated with
+// Mojang. One is stored on the client after login and again before it is
+// transferred, so the proxy it lands on can skip the Mojang round trip within
+// the trust window. Every proxy that should trust the cookie is configured
+// with the same secret.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::raw_packet;
+use crate::whitelist::Player;
+
+pub const COOKIE_KEY: &str = "purple_cello:session";
+// The client refuses larger cookies
+const MAX_COOKIE_SIZE: usize = 5120;
+const COOKIE_VERSION: u8 = 1;
+const SIGNATURE_SIZE: usize = 32;
+
+#[derive(Clone)]
+pub struct CookieConfig {
+    pub secret: Vec<u8>,
+    pub trust_window: Duration,
+}
+
+fn signature(config: &CookieConfig, data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&config.secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// The player's name, uuid and address, when the cookie was issued, and a
+// signature over all of it
+pub fn create(
+    config: &CookieConfig,
+    name: &str,
+    player_uuid: u128,
+    ip: IpAddr,
+    now: i64,
+) -> Vec<u8> {
+    let mut data = vec![COOKIE_VERSION];
+    data.extend_from_slice(&now.to_be_bytes());
+    data.extend_from_slice(&player_uuid.to_be_bytes());
+    raw_packet::write_string(&mut data, &ip.to_string());
+    raw_packet::write_string(&mut data, name);
+    let signature = signature(config, &data);
+    data.extend(signature);
+    data
+}
+
+// Whether the cookie was issued by a proxy sharing the secret, for this
+// player at this address, within the trust window
+pub fn verify(
+    config: &CookieConfig,
+    cookie: &[u8],
+    player: &Player,
+    ip: IpAddr,
+    now: i64,
+) -> bool {
+    if cookie.len() <= SIGNATURE_SIZE || cookie.len() > MAX_COOKIE_SIZE {
+        return false;
+    }
+    let (data, signed) = cookie.split_at(cookie.len() - SIGNATURE_SIZE);
+    let mut mac = Hmac::<Sha256>::new_from_slice(&config.secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(data);
+    if mac.verify_slice(signed).is_err() || data[0] != COOKIE_VERSION {
+        return false;
+    }
+
+    let mut index = 1;
+    let issued = match data.get(index..index + 8) {
+        Some(bytes) =>
+            i64::from_be_bytes(bytes.try_into().unwrap_or_default()),
+        None => return false,
+    };
+    index += 8;
+    let cookie_uuid = match data.get(index..index + 16) {
+        Some(bytes) =>
+            u128::from_be_bytes(bytes.try_into().unwrap_or_default()),
+        None => return false,
+    };
+    index += 16;
+    let cookie_ip = raw_packet::read_string(data, &mut index);
+    let cookie_name = raw_packet::read_string(data, &mut index);
+
+    let age = now - issued;
+    age >= 0 && (age as u64) <= config.trust_window.as_secs() &&
+        player.player_uuid == Some(cookie_uuid) &&
+        cookie_ip == Some(ip.to_string()) &&
+        cookie_name.as_deref() == Some(player.name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(secret: &str) -> CookieConfig {
+        CookieConfig {
+            secret: secret.as_bytes().to_vec(),
+            trust_window: Duration::from_secs(300),
+        }
+    }
+
+    fn player() -> Player {
+        Player {
+            name: "Purple".to_string(),
+            player_uuid: Some(42),
+            active: true,
+            group: None,
+        }
+    }
+
+    #[test]
+    fn trusted_within_window() {
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let cookie = create(&config("secret"), "Purple", 42, ip, 1000);
+
+        assert!(verify(&config("secret"), &cookie, &player(), ip, 1200));
+        // Too old
+        assert!(!verify(&config("secret"), &cookie, &player(), ip, 1301));
+        // Another proxy's secret
+        assert!(!verify(&config("other"), &cookie, &player(), ip, 1200));
+        // Another address
+        assert!(!verify(&config("secret"), &cookie, &player(),
+            "203.0.113.8".parse().unwrap(), 1200));
+    }
+
+    #[test]
+    fn tampered_cookie_rejected() {
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let mut cookie = create(&config("secret"), "Purple", 42, ip, 1000);
+        cookie[1] ^= 1;
+        assert!(!verify(&config("secret"), &cookie, &player(), ip, 1000));
+
+        let mut impostor = player();
+        impostor.name = "Cello".to_string();
+        let cookie = create(&config("secret"), "Purple", 42, ip, 1000);
+        assert!(!verify(&config("secret"), &cookie, &impostor, ip, 1000));
+    }
+}
@@ -0,0 +1,253 @@
+// Yeahbut October 2026
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde_json::Value;
+use tokio::sync::mpsc;
+use lazy_static::lazy_static;
+
+use crate::packet_ids;
+
+// Messages injected into a session's backend to client stream
+pub enum SessionMessage {
+    Chat(Value),
+    Kick(String),
+    // Reconnect to another address, 1.20.5+ clients only
+    Transfer(String, u16),
+}
+
+const DEFAULT_PORT: u16 = 25565;
+
+#[derive(Clone, Default)]
+pub struct ByteCounters {
+    pub to_backend: Arc<AtomicU64>,
+    pub to_client: Arc<AtomicU64>,
+}
+
+#[derive(Clone)]
+pub struct SessionInfo {
+    pub name: String,
+    pub player_uuid: Option<u128>,
+    pub protocol: i32,
+    pub client_addr: SocketAddr,
+    // Formatted address of the listener the player joined through
+    pub listener: String,
+    // Name of the backend the player is currently on
+    pub backend: String,
+    pub connected_at: SystemTime,
+    pub bytes: ByteCounters,
+}
+
+impl SessionInfo {
+    pub fn connected_for(&self) -> Duration {
+        self.connected_at.elapsed().unwrap_or_default()
+    }
+
+    // Transfer packets were added in 1.20.5
+    pub fn can_transfer(&self) -> bool {
+        packet_ids::play::clientbound::transfer(self.protocol).is_some()
+    }
+}
+
+pub enum TransferOutcome {
+    Sent,
+    ClientTooOld,
+    NotConnected,
+}
+
+struct SessionEntry {
+    info: SessionInfo,
+    sender: mpsc::UnboundedSender<SessionMessage>,
+}
+
+// Logins older than this no longer identify a pinging address
+const RECENT_LOGIN_DURATION: Duration = Duration::from_secs(7 * 24 * 3600);
+const RECENT_LOGIN_LIMIT: usize = 4096;
+
+lazy_static! {
+    static ref SESSIONS: Arc<Mutex<HashMap<u64, SessionEntry>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+    static ref RECENT_LOGINS: Arc<Mutex<HashMap<IpAddr, (String, Instant)>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+// Removes the session from the registry when dropped
+pub struct SessionGuard {
+    pub id: u64,
+}
+
+impl SessionGuard {
+    pub fn set_backend(&self, backend: &str) {
+        if let Some(session) = SESSIONS.lock().unwrap().get_mut(&self.id) {
+            session.info.backend = backend.to_string();
+        }
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        if let Some(session) = SESSIONS.lock().unwrap().remove(&self.id) {
+            println!("{} disconnected after {}s ({} bytes up, {} bytes down)",
+                session.info.name,
+                session.info.connected_for().as_secs(),
+                session.info.bytes.to_backend.load(Ordering::Relaxed),
+                session.info.bytes.to_client.load(Ordering::Relaxed),
+            );
+        }
+    }
+}
+
+pub fn register(
+    info: SessionInfo,
+) -> (SessionGuard, mpsc::UnboundedReceiver<SessionMessage>) {
+    let id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+    let (sender, receiver) = mpsc::unbounded_channel();
+    println!("{} connected from {} to {}",
+        info.name, info.client_addr, info.backend);
+    record_login(info.client_addr.ip(), &info.name);
+    SESSIONS.lock().unwrap().insert(id, SessionEntry { info, sender });
+    (SessionGuard { id }, receiver)
+}
+
+fn record_login(ip: IpAddr, name: &str) {
+    let mut recent = RECENT_LOGINS.lock().unwrap();
+    recent.retain(|_, (_, at)| at.elapsed() < RECENT_LOGIN_DURATION);
+    if recent.len() >= RECENT_LOGIN_LIMIT && !recent.contains_key(&ip) {
+        let oldest = recent.iter()
+            .min_by_key(|(_, (_, at))| *at)
+            .map(|(ip, _)| *ip);
+        if let Some(oldest) = oldest {
+            recent.remove(&oldest);
+        }
+    }
+    recent.insert(ip, (name.to_string(), Instant::now()));
+}
+
+// The player who most recently logged in from this address
+pub fn recent_player(ip: IpAddr) -> Option<String> {
+    RECENT_LOGINS.lock().unwrap().get(&ip)
+        .filter(|(_, at)| at.elapsed() < RECENT_LOGIN_DURATION)
+        .map(|(name, _)| name.clone())
+}
+
+pub fn online_players() -> Vec<SessionInfo> {
+    let mut players: Vec<SessionInfo> = SESSIONS.lock().unwrap().values()
+        .map(|session| session.info.clone())
+        .collect();
+    players.sort_by(|a, b| a.connected_at.cmp(&b.connected_at));
+    players
+}
+
+pub fn format_uuid(uuid: u128) -> String {
+    let hex = format!("{:032x}", uuid);
+    format!("{}-{}-{}-{}-{}",
+        &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+// Returns how many sessions the message was sent to. Players who are
+// configuring get it once they are back in play.
+pub fn broadcast(message: &Value) -> usize {
+    SESSIONS.lock().unwrap().values()
+        .filter(|session| session.sender
+            .send(SessionMessage::Chat(message.clone())).is_ok())
+        .count()
+}
+
+// Returns how many sessions were asked to transfer, and how many were left
+// alone because their client can't be transferred
+pub fn transfer_all(host: &str, port: u16) -> (usize, usize) {
+    let sessions = SESSIONS.lock().unwrap();
+    let (able, too_old): (Vec<&SessionEntry>, Vec<&SessionEntry>) =
+        sessions.values().partition(|session| session.info.can_transfer());
+    let sent = able.into_iter()
+        .filter(|session| session.sender
+            .send(SessionMessage::Transfer(host.to_string(), port)).is_ok())
+        .count();
+    (sent, too_old.len())
+}
+
+pub fn transfer(name: &str, host: &str, port: u16) -> TransferOutcome {
+    let sessions = SESSIONS.lock().unwrap();
+    match sessions.values()
+        .find(|session| session.info.name.eq_ignore_ascii_case(name)) {
+            Some(session) if !session.info.can_transfer() =>
+                TransferOutcome::ClientTooOld,
+            Some(session) if session.sender
+                .send(SessionMessage::Transfer(host.to_string(), port))
+                .is_ok() => TransferOutcome::Sent,
+            _ => TransferOutcome::NotConnected,
+    }
+}
+
+// Parses "host" or "host:port" for a transfer. IPv6 addresses are written
+// in brackets when they have a port, e.g. "[2001:db8::1]:25565".
+pub fn parse_address(address: &str) -> Option<(String, u16)> {
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        host.parse::<Ipv6Addr>().ok()?;
+        let port = match rest.strip_prefix(':') {
+            Some(port) => port.parse().ok()?,
+            None if rest.is_empty() => DEFAULT_PORT,
+            None => return None,
+        };
+        return Some((host.to_string(), port));
+    }
+    if address.parse::<Ipv6Addr>().is_ok() {
+        return Some((address.to_string(), DEFAULT_PORT));
+    }
+
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && !host.contains(':') =>
+            Some((host.to_string(), port.parse().ok()?)),
+        Some(_) => None,
+        None if !address.is_empty() =>
+            Some((address.to_string(), DEFAULT_PORT)),
+        None => None,
+    }
+}
+
+// Returns false if the player is not connected
+pub fn send_to(name: &str, message: SessionMessage) -> bool {
+    let sessions = SESSIONS.lock().unwrap();
+    match sessions.values()
+        .find(|session| session.info.name.eq_ignore_ascii_case(name)) {
+            Some(session) => session.sender.send(message).is_ok(),
+            None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(host: &str, port: u16) -> Option<(String, u16)> {
+        Some((host.to_string(), port))
+    }
+
+    #[test]
+    fn transfer_addresses() {
+        assert_eq!(parse_address("play.example.com"),
+            address("play.example.com", DEFAULT_PORT));
+        assert_eq!(parse_address("play.example.com:25570"),
+            address("play.example.com", 25570));
+        assert_eq!(parse_address("10.0.0.2:25570"), address("10.0.0.2", 25570));
+        assert_eq!(parse_address("[2001:db8::1]:25570"),
+            address("2001:db8::1", 25570));
+        assert_eq!(parse_address("[2001:db8::1]"),
+            address("2001:db8::1", DEFAULT_PORT));
+        assert_eq!(parse_address("2001:db8::1"),
+            address("2001:db8::1", DEFAULT_PORT));
+
+        assert_eq!(parse_address(""), None);
+        assert_eq!(parse_address(":25565"), None);
+        assert_eq!(parse_address("host:port"), None);
+        assert_eq!(parse_address("[2001:db8::1]25570"), None);
+        assert_eq!(parse_address("[not an address]:25565"), None);
+    }
+}
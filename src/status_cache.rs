@@ -0,0 +1,159 @@
+// Yeahbut October 2026
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+use purple_cello_mc_protocol::mc_types::ProtocolConnection;
+
+use crate::listener;
+use crate::status_handle;
+
+// How often each backend's status is refreshed
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+// A backend that does not answer within this long is treated as down
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(3);
+// Entries the refresher has not touched for this long are ignored
+const STALE_AFTER: Duration = Duration::from_secs(15);
+
+#[derive(Clone)]
+pub struct SamplePlayer {
+    pub name: String,
+    pub id: String,
+}
+
+#[derive(Clone)]
+pub struct UpstreamStatus {
+    pub version_name: String,
+    pub protocol: i32,
+    pub online: i32,
+    pub max: i32,
+    pub sample: Vec<SamplePlayer>,
+}
+
+// The combined status of every backend behind a listener
+pub struct AggregateStatus {
+    pub status: UpstreamStatus,
+    pub healthy: usize,
+    pub total: usize,
+}
+
+impl AggregateStatus {
+    // Some, but not all, backends are down
+    pub fn degraded(&self) -> bool {
+        self.healthy < self.total
+    }
+}
+
+struct CachedStatus {
+    status: Option<UpstreamStatus>,
+    // Kept while the backend is down so the proxy can still show it
+    last_known: Option<UpstreamStatus>,
+    timestamp: Instant,
+}
+
+lazy_static! {
+    static ref STATUS_CACHE: Arc<Mutex<HashMap<String, CachedStatus>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+// The last status fetched from the backend, or None if it was down
+pub fn cached_status(backend: &listener::Backend) -> Option<UpstreamStatus> {
+    STATUS_CACHE.lock().unwrap()
+        .get(&backend.formatted_address())
+        .filter(|cached| cached.timestamp.elapsed() < STALE_AFTER)
+        .and_then(|cached| cached.status.clone())
+}
+
+// The last status any of the backends answered with, however long ago
+pub fn last_known_status(
+    backends: &[listener::Backend],
+) -> Option<UpstreamStatus> {
+    let cache = STATUS_CACHE.lock().unwrap();
+    backends.iter()
+        .filter_map(|backend| cache.get(&backend.formatted_address()))
+        .find_map(|cached| cached.last_known.clone())
+}
+
+// Sums the counts and merges the samples of every healthy backend, or None if
+// all of them are down
+pub fn aggregate_status(backends: &[listener::Backend]) -> Option<AggregateStatus> {
+    let statuses: Vec<UpstreamStatus> = backends.iter()
+        .filter_map(cached_status)
+        .collect();
+    let first = statuses.first()?;
+
+    let mut aggregate = UpstreamStatus {
+        version_name: first.version_name.clone(),
+        protocol: first.protocol,
+        online: 0,
+        max: 0,
+        sample: Vec::new(),
+    };
+    for status in &statuses {
+        aggregate.online += status.online;
+        aggregate.max += status.max;
+        for player in &status.sample {
+            if !aggregate.sample.iter().any(|seen| seen.id == player.id) {
+                aggregate.sample.push(player.clone());
+            }
+        }
+    }
+
+    Some(AggregateStatus {
+        status: aggregate,
+        healthy: statuses.len(),
+        total: backends.len(),
+    })
+}
+
+async fn fetch_status(
+    proxy_info: &listener::ProxyInfo,
+) -> Option<UpstreamStatus> {
+    let backend_stream = proxy_info.backend.connect(None).await.ok()?;
+    let (mut backend_reader, mut backend_writer) = backend_stream.into_split();
+    let mut server_conn = ProtocolConnection::new(
+        &mut backend_reader,
+        &mut backend_writer,
+    );
+    let status = status_handle::get_upstream_status(
+        proxy_info.clone(), &mut server_conn).await.ok()?;
+
+    Some(UpstreamStatus {
+        version_name: status.version.name,
+        protocol: status.version.protocol,
+        online: status.players.online,
+        max: status.players.max,
+        sample: status.players.sample.unwrap_or_default().into_iter()
+            .map(|player| SamplePlayer {
+                name: player.name,
+                id: player.id,
+            })
+            .collect(),
+    })
+}
+
+// Keeps the cached status of the listener's backend up to date
+pub async fn refresher(proxy_info: listener::ProxyInfo) {
+    let backend = proxy_info.formatted_backend_address();
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let status = tokio::time::timeout(
+            REFRESH_TIMEOUT, fetch_status(&proxy_info)).await
+                .unwrap_or(None);
+
+        let mut cache = STATUS_CACHE.lock().unwrap();
+        let last_known = status.clone().or_else(|| cache.get(&backend)
+            .and_then(|cached| cached.last_known.clone()));
+        cache.insert(backend.clone(), CachedStatus {
+            status,
+            last_known,
+            timestamp: Instant::now(),
+        });
+    }
+}
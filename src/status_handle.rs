@@ -1,28 +1,165 @@
 // Yeahbut December 2023
 
-use tokio::io::AsyncWriteExt;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+use serde_json::{json, Value};
 
 use purple_cello_mc_protocol::{
-    mc_types::{self, Result, Packet, ProtocolConnection},
+    mc_types::{self, Result, Packet, ProtocolConnection, ProtocolWrite},
     handshake,
     status,
 };
 
 use crate::listener;
 use crate::info_messages;
-use crate::motd::{motd, favicon};
+use crate::motd::{motd, offline_motd, icon_selection, MotdContext};
+use crate::favicon::favicon;
+use crate::wake;
+use crate::sessions;
+use crate::status_cache;
+use crate::raw_packet;
+use crate::chat;
+use crate::legacy_ping;
+use crate::versions;
 
-async fn online_players(
-    proxy_info: listener::ProxyInfo,
-    server_conn: &mut ProtocolConnection<'_>,
-) -> Result<status::clientbound::StatusPlayers> {
-    Ok(get_upstream_status(proxy_info, server_conn).await?.players)
+// The vanilla server shows at most 12 players in the sample
+const SAMPLE_SIZE: usize = 12;
+
+// Players connected through the proxy, or the backends' own samples if none
+fn player_sample(
+    proxy_info: &listener::ProxyInfo,
+    degraded: bool,
+    upstream_sample: Vec<status_cache::SamplePlayer>,
+) -> Option<Vec<Value>> {
+    let backends = proxy_info.status_backends();
+    let mut sample: Vec<Value> =
+        sessions::online_players().into_iter()
+            .filter(|session| backends.iter()
+                .any(|backend| backend.name == session.backend))
+            .take(SAMPLE_SIZE)
+            .map(|session| json!({
+                "name": session.name,
+                // Clients reject the whole response over an invalid id
+                "id": sessions::format_uuid(
+                    session.player_uuid.unwrap_or_default()),
+            }))
+            .collect();
+
+    if sample.is_empty() {
+        sample = upstream_sample.into_iter()
+            .take(SAMPLE_SIZE)
+            .map(|player| json!({
+                "name": player.name,
+                "id": player.id,
+            }))
+            .collect();
+    }
+
+    if degraded {
+        sample.insert(0, json!({
+            "name": info_messages::BACKENDS_DEGRADED_SAMPLE,
+            "id": sessions::format_uuid(0),
+        }));
+        sample.truncate(SAMPLE_SIZE);
+    }
+
+    if sample.is_empty() {
+        None
+    } else {
+        Some(sample)
+    }
+}
+
+pub fn motd_context(
+    aggregate: &status_cache::AggregateStatus,
+    client_addr: SocketAddr,
+) -> MotdContext {
+    MotdContext {
+        online: aggregate.status.online,
+        max: aggregate.status.max,
+        version: aggregate.status.version_name.clone(),
+        backend_status: if aggregate.degraded() {
+            "degraded".to_string()
+        } else {
+            "online".to_string()
+        },
+        player_name: sessions::recent_player(client_addr.ip()),
+    }
+}
+
+// The client's own version if the backend accepts it, so it is not shown as
+// incompatible, otherwise the releases it does accept
+fn status_version(
+    proxy_info: &listener::ProxyInfo,
+    client_protocol: i32,
+) -> Value {
+    let accepted = &proxy_info.backend.protocols.supported();
+    if accepted.accepts(client_protocol) {
+        json!({
+            "name": versions::version_name(client_protocol),
+            "protocol": client_protocol,
+        })
+    } else {
+        json!({
+            "name": format!("requires {}", versions::range_name(accepted)),
+            "protocol": accepted.newest(),
+        })
+    }
+}
+
+// Every backend is down: show the last status they answered with, so the
+// client still sees the right version, under the offline MOTD
+fn offline_status(
+    proxy_info: &listener::ProxyInfo,
+    client_addr: SocketAddr,
+    client_protocol: i32,
+) -> Value {
+    let starting = wake::is_starting(proxy_info);
+    let (version_name, max) =
+        match status_cache::last_known_status(&proxy_info.status_backends()) {
+            Some(status) => (status.version_name, status.max),
+            None => (mc_types::VERSION_NAME.to_string(), 0),
+    };
+
+    let context = MotdContext {
+        online: 0,
+        max,
+        version: version_name,
+        backend_status: if starting {
+            "starting".to_string()
+        } else {
+            "offline".to_string()
+        },
+        player_name: sessions::recent_player(client_addr.ip()),
+    };
+    let description = offline_motd(&context, starting)
+        .unwrap_or_else(|| json!({
+            "text": if starting {
+                info_messages::BACKEND_STARTING_PING
+            } else {
+                info_messages::BACKEND_DOWN_PING
+            },
+        }));
+
+    json!({
+        "version": status_version(proxy_info, client_protocol),
+        "description": description,
+        "players": {
+            "max": max,
+            "online": 0,
+        },
+        "enforcesSecureChat": false,
+        "previewsChat": false,
+    })
 }
 
 pub async fn respond_status(
     proxy_info: listener::ProxyInfo,
+    client_addr: SocketAddr,
+    client_protocol: i32,
     client_conn: &mut ProtocolConnection<'_>,
-    server_conn: &mut Option<ProtocolConnection<'_>>,
 )-> Result<()> {
     loop {
         println!("Status Handling");
@@ -31,63 +168,43 @@ pub async fn respond_status(
         match packet {
             status::serverbound::StatusPackets::Status(_) => {
                 println!("Handling Status");
-                let favicon = favicon();
-
-                let online_players = match server_conn {
-                    Some(server_conn) =>
-                        match online_players(
-                            proxy_info.clone(),
-                            server_conn,
-                        ).await {
-                            Ok(value) => Some(value),
-                            Err(_) => None,
-                        },
-                    None => None,
-                };
+                let favicon = favicon(icon_selection());
 
-                let status_response =
-                    match online_players {
-                        Some(online_players) =>
-                            status::clientbound::StatusResponseData {
-                                version: status::clientbound::StatusVersion {
-                                    name: mc_types::VERSION_NAME.to_string(),
-                                    protocol: mc_types::VERSION_PROTOCOL,
-                                },
-                                description: mc_types::Chat {
-                                    text: motd(),
-                                },
-                                players: status::clientbound::StatusPlayers {
-                                    max: -13,
-                                    online: online_players.online,
-                                    sample: online_players.sample,
+                let aggregate = status_cache::aggregate_status(
+                    &proxy_info.status_backends());
+
+                let mut status_response =
+                    match aggregate {
+                        Some(aggregate) => {
+                            let context = motd_context(&aggregate, client_addr);
+                            let mut response = json!({
+                                "version": status_version(
+                                    &proxy_info, client_protocol),
+                                "description": motd(&context),
+                                "players": {
+                                    "max": aggregate.status.max,
+                                    "online": aggregate.status.online,
                                 },
-                                favicon: favicon,
-                                enforcesSecureChat: Some(false),
-                                previewsChat: Some(false),
-                        },
-                        None => status::clientbound::StatusResponseData {
-                            version: status::clientbound::StatusVersion {
-                                name: "Old".to_string(),
-                                protocol: 0,
-                            },
-                            description: mc_types::Chat {
-                                text: info_messages::BACKEND_DOWN_PING
-                                    .to_string(),
-                            },
-                            players: status::clientbound::StatusPlayers {
-                                max: 0,
-                                online: 0,
-                                sample: None,
-                            },
-                            favicon: favicon,
-                            enforcesSecureChat: Some(false),
-                            previewsChat: Some(false),
+                                "enforcesSecureChat": false,
+                                "previewsChat": false,
+                            });
+                            if let Some(sample) = player_sample(
+                                &proxy_info,
+                                aggregate.degraded(),
+                                aggregate.status.sample,
+                            ) {
+                                response["players"]["sample"] = json!(sample);
+                            }
+                            response
                         },
+                        None => offline_status(
+                            &proxy_info, client_addr, client_protocol),
                 };
+                if let Some(favicon) = favicon {
+                    status_response["favicon"] = json!(favicon);
+                }
 
-                let packet =
-                    status::clientbound::Status::from_json(status_response)?;
-                packet.write(client_conn).await?;
+                write_status_response(client_conn, &status_response).await?;
             },
             status::serverbound::StatusPackets::Ping(packet) => {
                 println!("Handling Ping");
@@ -102,14 +219,24 @@ pub async fn respond_status(
     Ok(())
 }
 
+// Written by hand so the description can be any chat component
+async fn write_status_response(
+    client_conn: &mut ProtocolConnection<'_>,
+    status_response: &Value,
+) -> Result<()> {
+    let mut data = raw_packet::new_packet(0x00);
+    raw_packet::write_string(&mut data, &status_response.to_string());
+    client_conn.write_data(&mut data).await
+}
+
 pub async fn get_upstream_status(
     proxy_info: listener::ProxyInfo,
     server_conn: &mut ProtocolConnection<'_>,
 ) -> Result<status::clientbound::StatusResponseData> {
     handshake::serverbound::Handshake{
-        protocol_version: mc_types::VERSION_PROTOCOL,
-        server_address: proxy_info.backend_addr,
-        server_port: proxy_info.backend_port,
+        protocol_version: proxy_info.backend.protocols.newest(),
+        server_address: proxy_info.backend.addr,
+        server_port: proxy_info.backend.port,
         next_state: 1,
     }.write(server_conn).await?;
     status::serverbound::Status{}.write(server_conn).await?;
@@ -119,24 +246,64 @@ pub async fn get_upstream_status(
     Ok(status_response)
 }
 
+// Old clients send the rest of the request without waiting, beta clients send
+// nothing after 0xFE
+const LEGACY_READ_TIMEOUT: Duration = Duration::from_millis(200);
+const LEGACY_REQUEST_LIMIT: usize = 512;
+
 pub async fn respond_legacy_status(
+    proxy_info: listener::ProxyInfo,
+    client_addr: SocketAddr,
     client_conn: &mut ProtocolConnection<'_>,
 ) -> Result<()> {
     println!("Old Style Status");
-    client_conn.stream_write.write_u8(0xFF).await?;
-
-    let s = "§1\0127\0".to_string() +
-        mc_types::VERSION_NAME +
-        "\0YTD Proxy§0§10";
-    let utf16_vec: Vec<u16> = s
-        .encode_utf16()
-        .flat_map(|c| std::iter::once(c).chain(std::iter::once(0)))
-        .collect();
-
-    client_conn.stream_write.write_u16((utf16_vec.len() / 2) as u16).await?;
-    for utf16_char in utf16_vec {
-        client_conn.stream_write.write_u16(utf16_char).await?;
+
+    let mut request = Vec::new();
+    let mut buffer = [0; 256];
+    while !legacy_ping::is_complete(&request) &&
+        request.len() < LEGACY_REQUEST_LIMIT {
+            match timeout(
+                LEGACY_READ_TIMEOUT,
+                client_conn.stream_read.read(&mut buffer),
+            ).await {
+                Ok(Ok(0)) | Ok(Err(_)) | Err(_) => break,
+                Ok(Ok(length)) => request.extend_from_slice(&buffer[..length]),
+            }
     }
 
+    let ping = legacy_ping::parse(&request);
+    if let legacy_ping::LegacyPing::V1_6 { protocol, host, port } = &ping {
+        println!("Legacy ping for {}:{} from protocol {}", host, port, protocol);
+    }
+
+    let aggregate = status_cache::aggregate_status(
+        &proxy_info.status_backends());
+    let status = match aggregate {
+        Some(aggregate) => legacy_ping::LegacyStatus {
+            protocol: mc_types::VERSION_PROTOCOL,
+            version: aggregate.status.version_name.clone(),
+            // The old server list only has room for one line
+            motd: chat::legacy_text(
+                &motd(&motd_context(&aggregate, client_addr)),
+            ).replace('\n', " "),
+            online: aggregate.status.online,
+            max: aggregate.status.max,
+        },
+        None => legacy_ping::LegacyStatus {
+            protocol: 0,
+            version: "Old".to_string(),
+            motd: if wake::is_starting(&proxy_info) {
+                info_messages::BACKEND_STARTING_PING
+            } else {
+                info_messages::BACKEND_DOWN_PING
+            }.replace('\n', " "),
+            online: 0,
+            max: 0,
+        },
+    };
+
+    client_conn.stream_write.write_all(
+        &legacy_ping::response(&ping, &status)).await?;
+
     Ok(())
 }
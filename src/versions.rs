@@ -0,0 +1,102 @@
+// Yeahbut October 2026
+
+use crate::listener::ProtocolVersions;
+
+// Release names for each protocol version, oldest first. Several releases
+// often share a protocol.
+const VERSION_NAMES: [(i32, &[&str]); 46] = [
+    (47, &["1.8", "1.8.9"]),
+    (107, &["1.9"]),
+    (108, &["1.9.1"]),
+    (109, &["1.9.2"]),
+    (110, &["1.9.3", "1.9.4"]),
+    (210, &["1.10", "1.10.2"]),
+    (315, &["1.11"]),
+    (316, &["1.11.1", "1.11.2"]),
+    (335, &["1.12"]),
+    (338, &["1.12.1"]),
+    (340, &["1.12.2"]),
+    (393, &["1.13"]),
+    (401, &["1.13.1"]),
+    (404, &["1.13.2"]),
+    (477, &["1.14"]),
+    (480, &["1.14.1"]),
+    (485, &["1.14.2"]),
+    (490, &["1.14.3"]),
+    (498, &["1.14.4"]),
+    (573, &["1.15"]),
+    (575, &["1.15.1"]),
+    (578, &["1.15.2"]),
+    (735, &["1.16"]),
+    (736, &["1.16.1"]),
+    (751, &["1.16.2"]),
+    (753, &["1.16.3"]),
+    (754, &["1.16.4", "1.16.5"]),
+    (755, &["1.17"]),
+    (756, &["1.17.1"]),
+    (757, &["1.18", "1.18.1"]),
+    (758, &["1.18.2"]),
+    (759, &["1.19"]),
+    (760, &["1.19.1", "1.19.2"]),
+    (761, &["1.19.3"]),
+    (762, &["1.19.4"]),
+    (763, &["1.20", "1.20.1"]),
+    (764, &["1.20.2"]),
+    (765, &["1.20.3", "1.20.4"]),
+    (766, &["1.20.5", "1.20.6"]),
+    (767, &["1.21", "1.21.1"]),
+    (768, &["1.21.2", "1.21.3"]),
+    (769, &["1.21.4"]),
+    (770, &["1.21.5"]),
+    (771, &["1.21.6"]),
+    (772, &["1.21.7", "1.21.8"]),
+    (773, &["1.21.9", "1.21.10"]),
+];
+
+fn releases(protocol: i32) -> Option<&'static [&'static str]> {
+    VERSION_NAMES.iter()
+        .find(|(known, _)| *known == protocol)
+        .map(|(_, names)| *names)
+}
+
+// The newest release using the protocol, e.g. "1.21.1" for 767
+pub fn version_name(protocol: i32) -> String {
+    releases(protocol)
+        .and_then(|names| names.last())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("protocol {}", protocol))
+}
+
+fn oldest_release(protocol: i32) -> String {
+    releases(protocol)
+        .and_then(|names| names.first())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("protocol {}", protocol))
+}
+
+// Every release a backend accepts, e.g. "1.20.3-1.21.1" or just "1.21.1"
+pub fn range_name(accepted: &ProtocolVersions) -> String {
+    let (oldest, newest) = (accepted.oldest(), accepted.newest());
+    if oldest == newest {
+        version_name(newest)
+    } else {
+        format!("{}\u{2013}{}", oldest_release(oldest), version_name(newest))
+    }
+}
+
+// Disconnect templates for clients the backend does not accept
+#[derive(Clone)]
+pub struct VersionMessages {
+    pub outdated: String,
+    pub future: String,
+}
+
+// Fills {client_version} and {supported} in a version disconnect template
+pub fn fill_template(
+    template: &str,
+    client_protocol: i32,
+    accepted: &ProtocolVersions,
+) -> String {
+    template.replace("{client_version}", &version_name(client_protocol))
+        .replace("{supported}", &range_name(accepted))
+}
@@ -0,0 +1,249 @@
+// Yeahbut October 2026
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::process::Command;
+use lazy_static::lazy_static;
+
+use purple_cello_mc_protocol::mc_types::ProtocolConnection;
+
+use crate::listener;
+use crate::status_handle;
+
+// How often the backend is polled while it is starting
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+// How often the idle monitor checks the player count
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub struct WakeConfig {
+    pub start_command: Vec<String>,
+    pub stop_command: Vec<String>,
+    // How long a joining player is held before being told to retry
+    pub join_wait: Duration,
+    // Stop the backend after no players have been connected this long
+    pub idle_timeout: Option<Duration>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum BackendState {
+    Unknown,
+    Starting,
+    Running,
+    Stopped,
+}
+
+struct WakeState {
+    state: BackendState,
+    // Whether the start command is still running, usually as the server
+    start_running: bool,
+    players: usize,
+    last_player: Instant,
+}
+
+impl WakeState {
+    fn new() -> Self {
+        Self {
+            state: BackendState::Unknown,
+            start_running: false,
+            players: 0,
+            last_player: Instant::now(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref WAKE_STATES: Arc<Mutex<HashMap<String, WakeState>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+fn with_state<T>(backend: &str, f: impl FnOnce(&mut WakeState) -> T) -> T {
+    let mut states = WAKE_STATES.lock().unwrap();
+    let state = states.entry(backend.to_string())
+        .or_insert_with(WakeState::new);
+    f(state)
+}
+
+pub fn backend_state(backend: &str) -> BackendState {
+    with_state(backend, |state| state.state)
+}
+
+pub fn is_starting(proxy_info: &listener::ProxyInfo) -> bool {
+    proxy_info.wake.is_some() && backend_state(
+        &proxy_info.formatted_backend_address()) == BackendState::Starting
+}
+
+pub fn player_joined(backend: &str) {
+    with_state(backend, |state| {
+        state.players += 1;
+        state.state = BackendState::Running;
+        state.last_player = Instant::now();
+    });
+}
+
+pub fn player_left(backend: &str) {
+    with_state(backend, |state| {
+        state.players = state.players.saturating_sub(1);
+        state.last_player = Instant::now();
+    });
+}
+
+async fn run_command(command: &[String]) -> bool {
+    let (program, args) = match command.split_first() {
+        Some(command) => command,
+        None => return false,
+    };
+    match Command::new(program).args(args).status().await {
+        Ok(status) => status.success(),
+        Err(e) => {
+            println!("Failed to run {}: {}", program, e);
+            false
+        },
+    }
+}
+
+// Start scripts usually keep running as the server itself, so the command is
+// left running and reaped in the background. If it fails before the server
+// comes up, the start is treated as failed.
+fn spawn_start_command(command: &[String], backend: &str) -> bool {
+    let (program, args) = match command.split_first() {
+        Some(command) => command,
+        None => return false,
+    };
+    let mut child = match Command::new(program).args(args).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            println!("Failed to run {}: {}", program, e);
+            return false;
+        },
+    };
+
+    let backend = backend.to_string();
+    with_state(&backend, |state| state.start_running = true);
+    tokio::spawn(async move {
+        let success = matches!(child.wait().await,
+            Ok(status) if status.success());
+        with_state(&backend, |state| {
+            state.start_running = false;
+            if !success && state.state == BackendState::Starting {
+                println!("Start command for {} failed", backend);
+                state.state = BackendState::Stopped;
+            }
+        });
+    });
+    true
+}
+
+// Runs the start command unless a start is already in progress or the last
+// one is still running, so two servers never share a port and world
+pub fn start_backend(proxy_info: &listener::ProxyInfo) {
+    let config = match &proxy_info.wake {
+        Some(config) => config,
+        None => return,
+    };
+    let backend = proxy_info.formatted_backend_address();
+
+    let should_start = with_state(&backend, |state| {
+        if state.state == BackendState::Starting || state.start_running {
+            false
+        } else {
+            state.state = BackendState::Starting;
+            state.last_player = Instant::now();
+            true
+        }
+    });
+
+    if should_start {
+        println!("Starting backend {}", backend);
+        if !spawn_start_command(&config.start_command, &backend) {
+            println!("Start command for {} failed", backend);
+            with_state(&backend, |state| state.state = BackendState::Stopped);
+        }
+    }
+}
+
+async fn backend_responds(proxy_info: &listener::ProxyInfo) -> bool {
+    let backend_stream = match proxy_info.backend.connect(None).await {
+            Ok(backend_stream) => backend_stream,
+            Err(_) => return false,
+    };
+    let (mut backend_reader, mut backend_writer) = backend_stream.into_split();
+    let mut server_conn = ProtocolConnection::new(
+        &mut backend_reader,
+        &mut backend_writer,
+    );
+    status_handle::get_upstream_status(proxy_info.clone(), &mut server_conn)
+        .await.is_ok()
+}
+
+// Starts the backend and waits until it answers a status request, or until
+// the configured join wait runs out
+pub async fn wait_for_backend(proxy_info: &listener::ProxyInfo) -> bool {
+    let config = match &proxy_info.wake {
+        Some(config) => config,
+        None => return false,
+    };
+    let backend = proxy_info.formatted_backend_address();
+
+    start_backend(proxy_info);
+
+    let deadline = Instant::now() + config.join_wait;
+    while Instant::now() < deadline {
+        if backend_state(&backend) == BackendState::Stopped {
+            return false;
+        }
+        if backend_responds(proxy_info).await {
+            println!("Backend {} is up", backend);
+            with_state(&backend, |state| state.state = BackendState::Running);
+            return true;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    // While the start command still runs the server may yet come up, so the
+    // state stays Starting. Once it has exited the next join may retry.
+    println!("Backend {} did not come up in time", backend);
+    with_state(&backend, |state| {
+        if state.state == BackendState::Starting && !state.start_running {
+            state.state = BackendState::Stopped;
+        }
+    });
+    false
+}
+
+// Stops the backend once no players have been connected for the idle timeout
+pub async fn idle_monitor(proxy_info: listener::ProxyInfo) {
+    let config = match &proxy_info.wake {
+        Some(config) => config.clone(),
+        None => return,
+    };
+    let idle_timeout = match config.idle_timeout {
+        Some(idle_timeout) => idle_timeout,
+        None => return,
+    };
+    let backend = proxy_info.formatted_backend_address();
+
+    loop {
+        tokio::time::sleep(IDLE_CHECK_INTERVAL).await;
+
+        let should_stop = with_state(&backend, |state| {
+            if state.players == 0 &&
+                state.state != BackendState::Stopped &&
+                state.last_player.elapsed() >= idle_timeout {
+                    state.state = BackendState::Stopped;
+                    true
+            } else {
+                false
+            }
+        });
+
+        if should_stop {
+            println!("Backend {} idle, stopping", backend);
+            if !run_command(&config.stop_command).await {
+                println!("Stop command for {} failed", backend);
+            }
+        }
+    }
+}
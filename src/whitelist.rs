@@ -9,11 +9,12 @@ use crate::info_messages;
 // Refresh every 5 minutes
 const EXPIRATION_DURATION: Duration = Duration::from_secs(300);
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 pub struct Player {
     pub name: String,
     pub player_uuid: Option<u128>,
     pub active: bool,
+    pub group: Option<String>,
 }
 
 pub enum PlayerAllowed {
@@ -133,10 +134,15 @@ impl WhitelistFile {
                 None => { false }
             };
 
+            let group = player_map.get("group")
+                .and_then(|group| group.as_str())
+                .map(|group| group.to_string());
+
             whitelist.push(Player {
                 name: name.to_string(),
                 player_uuid: Some(player_uuid),
                 active: active,
+                group: group,
             });
         }
 
@@ -160,7 +166,10 @@ impl WhitelistFile {
             if wl_player.name == player.name &&
                 wl_player.player_uuid == player.player_uuid {
                     if wl_player.active {
-                        return PlayerAllowed::True(player);
+                        return PlayerAllowed::True(Player {
+                            group: wl_player.group,
+                            ..player
+                        });
                     } else {
                         is_inactive = true;
                     }